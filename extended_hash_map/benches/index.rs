@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use extended_hash_map::{ExtendedHashMap, Query};
+
+const LEN: usize = 1_000_000;
+const CONDITION: &str = "$0 >= 500000, $0 < 500100, $1 < 500";
+
+fn map() -> ExtendedHashMap<&'static str, usize> {
+    let mut map = ExtendedHashMap::new();
+
+    for i in 0..LEN {
+        let key = format!("({}, {})", i, i % 1000);
+        map.insert(&*Box::leak(key.into_boxed_str()), i);
+    }
+    map
+}
+
+fn indexed(c: &mut Criterion) {
+    let mut map = map();
+    let query = Query::compile(CONDITION).unwrap();
+    let mut group = c.benchmark_group("1M tuple keys");
+
+    // every key from 500000 to 500099 has a second component below 500
+    assert_eq!(map.select(&query).len(), 100);
+
+    group.sample_size(10);
+    group.bench_function("scan", |b| b.iter(|| map.select(black_box(&query)).len()));
+
+    map.create_index(0);
+    map.create_index(1);
+    assert_eq!(map.select(&query).len(), 100);
+
+    group.bench_function("indexed", |b| {
+        b.iter(|| map.select(black_box(&query)).len())
+    });
+    group.finish();
+}
+
+fn spatial(c: &mut Criterion) {
+    let mut map = ExtendedHashMap::new();
+
+    for i in 0..LEN {
+        let key = format!("({}, {})", i % 1000, i / 1000);
+        map.insert(&*Box::leak(key.into_boxed_str()), i);
+    }
+
+    let query = Query::compile("$0 >= 500, $0 < 510, $1 >= 500, $1 < 510").unwrap();
+    let mut group = c.benchmark_group("1M points");
+
+    assert_eq!(map.select(&query).len(), 100);
+
+    group.sample_size(10);
+    group.bench_function("scan", |b| b.iter(|| map.select(black_box(&query)).len()));
+    group.bench_function("nearest scan", |b| {
+        b.iter(|| map.nearest(black_box(&[500.5, 500.5]), 10).len())
+    });
+
+    map.create_spatial_index(2);
+    assert_eq!(map.select(&query).len(), 100);
+
+    group.bench_function("spatial", |b| {
+        b.iter(|| map.select(black_box(&query)).len())
+    });
+    group.bench_function("nearest spatial", |b| {
+        b.iter(|| map.nearest(black_box(&[500.5, 500.5]), 10).len())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, indexed, spatial);
+criterion_main!(benches);
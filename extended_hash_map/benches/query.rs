@@ -0,0 +1,40 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use extended_hash_map::{ExtendedHashMap, Query};
+
+const CONDITION: &str = "$0 >= 100, $1 % 3 = 0, $2 like 'k1%'";
+
+fn map(len: usize) -> ExtendedHashMap<&'static str, usize> {
+    let mut map = ExtendedHashMap::new();
+
+    for i in 0..len {
+        let key = format!("{}, {}, 'k{}'", i, i * 7, i);
+        map.insert(&*Box::leak(key.into_boxed_str()), i);
+    }
+    map
+}
+
+fn repeated(c: &mut Criterion) {
+    let maps = (0..16).map(|_| map(64)).collect::<Vec<_>>();
+    let mut group = c.benchmark_group("16 maps of 64 keys");
+
+    group.bench_function("ploc", |b| {
+        b.iter(|| {
+            maps.iter()
+                .map(|map| map.ploc(black_box(CONDITION)).len())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("select", |b| {
+        let query = Query::compile(CONDITION).unwrap();
+
+        b.iter(|| {
+            maps.iter()
+                .map(|map| map.select(black_box(&query)).len())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, repeated);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+//! The parsed form of keys and conditions, for inspecting, building and
+//! rewriting conditions without going through their text. A condition
+//! tree prints back to canonical text that parses to the same nodes.
+
+pub use crate::expr_ast::{
+    node::{ApproxEq, BinOp, Component, Expr, Is, IsKind, Match, Node, SpanTree, Tree, UnaryOp},
+    pattern::{Pattern, Wildcard},
+    token::{
+        literal::Literal,
+        op::{ArithOp, OpKind},
+        Span,
+    },
+    visit::{rebuild_expr, rebuild_node, walk_expr, walk_node, Fold, Visitor},
+};
+
+#[cfg(feature = "regex")]
+pub use crate::expr_ast::pattern::Regex;
+
+use crate::expr_ast::{Parser, ParserErr};
+
+/// Parses a condition as `ploc` does, with one node per predicate.
+pub fn parse_condition(condition: &str) -> Result<Tree, ParserErr> {
+    Parser::new().parse_condition(condition)
+}
@@ -0,0 +1,390 @@
+//! Conditions built in code rather than parsed, producing the same nodes
+//! the parser does for the equivalent text.
+
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+};
+
+use super::{
+    node::{ApproxEq, BinOp, Component, Expr, Is, IsKind, Match, Node, Tree, UnaryOp},
+    pattern::{Pattern, Wildcard},
+    token::{
+        literal::Literal,
+        op::{ArithOp, OpKind},
+    },
+};
+
+/// A condition: predicates that must all hold. Start one from an operand,
+/// e.g. `Cond::component(0).ge(1).and(Cond::component(1).lt(5))`.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Cond {
+    predicates: Vec<Node>,
+}
+
+impl Cond {
+    /// The key component at `position`, like `$0`.
+    pub fn component(position: usize) -> Operand {
+        Operand(Expr::Component(Component::Position(position)))
+    }
+
+    /// A component nested in tuples, like `$0.1`.
+    pub fn path(path: &[usize]) -> Operand {
+        match *path {
+            [position] => Self::component(position),
+            _ => Operand(Expr::Component(Component::Path(path.to_vec()))),
+        }
+    }
+
+    /// The component the map's schema names `name`.
+    pub fn name(name: &str) -> Operand {
+        Operand(Expr::Component(Component::Name(name.to_string())))
+    }
+
+    pub fn value(value: impl Into<Operand>) -> Operand {
+        value.into()
+    }
+
+    /// An ISO-8601 date or datetime, as written in a condition.
+    pub fn datetime(text: &str) -> Operand {
+        Operand(Expr::Literal(Literal::DateTime(text.to_string())))
+    }
+
+    /// An ISO-8601 duration, as written in a condition.
+    pub fn duration(text: &str) -> Operand {
+        Operand(Expr::Literal(Literal::Duration(text.to_string())))
+    }
+
+    pub fn tuple<I>(items: I) -> Operand
+    where
+        I: IntoIterator,
+        I::Item: Into<Operand>,
+    {
+        Operand(Expr::Tuple(
+            items.into_iter().map(|item| item.into().0).collect(),
+        ))
+    }
+
+    /// Both conditions, with the predicates of `self` first.
+    pub fn and(mut self, other: Cond) -> Cond {
+        self.predicates.extend(other.predicates);
+        self
+    }
+
+    pub fn into_tree(self) -> Tree {
+        Tree {
+            root: self.predicates,
+            ..Default::default()
+        }
+    }
+
+    fn predicate(node: Node) -> Cond {
+        Cond {
+            predicates: vec![node],
+        }
+    }
+}
+
+impl From<Cond> for Tree {
+    fn from(cond: Cond) -> Self {
+        cond.into_tree()
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let predicates = self.predicates.iter().map(Node::to_string);
+        f.write_str(&predicates.collect::<Vec<_>>().join(", "))
+    }
+}
+
+/// An expression to compare, combined with arithmetic operators.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Operand(Expr);
+
+impl Operand {
+    /// `self = rhs`, named apart from `PartialEq::eq`, which compares the
+    /// operands themselves.
+    pub fn eq_to(self, rhs: impl Into<Operand>) -> Cond {
+        self.compare(OpKind::Eq, rhs)
+    }
+
+    /// `self <> rhs`.
+    pub fn ne_to(self, rhs: impl Into<Operand>) -> Cond {
+        self.compare(OpKind::Ne, rhs)
+    }
+
+    pub fn lt(self, rhs: impl Into<Operand>) -> Cond {
+        self.compare(OpKind::Lt, rhs)
+    }
+
+    pub fn le(self, rhs: impl Into<Operand>) -> Cond {
+        self.compare(OpKind::Le, rhs)
+    }
+
+    pub fn gt(self, rhs: impl Into<Operand>) -> Cond {
+        self.compare(OpKind::Gt, rhs)
+    }
+
+    pub fn ge(self, rhs: impl Into<Operand>) -> Cond {
+        self.compare(OpKind::Ge, rhs)
+    }
+
+    /// `self ~= rhs`, within the map's tolerance.
+    pub fn approx(self, rhs: impl Into<Operand>) -> Cond {
+        self.approx_eq(rhs, None)
+    }
+
+    /// `self ~= rhs ± tolerance`.
+    pub fn approx_within(self, rhs: impl Into<Operand>, tolerance: impl Into<Operand>) -> Cond {
+        self.approx_eq(rhs, Some(tolerance.into().0))
+    }
+
+    pub fn like(self, pattern: &str) -> Cond {
+        self.matching(Pattern::Like(Wildcard::like(pattern)))
+    }
+
+    pub fn glob(self, pattern: &str) -> Cond {
+        self.matching(Pattern::Glob(Wildcard::glob(pattern)))
+    }
+
+    #[cfg(feature = "regex")]
+    pub fn regex(self, pattern: &str) -> Result<Cond, super::ParserErr> {
+        use super::pattern::Regex;
+
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(self.matching(Pattern::Regex(regex))),
+            Err(_) => Err(super::ParserErr::InvalidPattern(pattern.to_string())),
+        }
+    }
+
+    pub fn is_null(self) -> Cond {
+        self.is(IsKind::Null, false)
+    }
+
+    pub fn is_not_null(self) -> Cond {
+        self.is(IsKind::Null, true)
+    }
+
+    pub fn is_nan(self) -> Cond {
+        self.is(IsKind::NaN, false)
+    }
+
+    pub fn is_true(self) -> Cond {
+        self.is(IsKind::True, false)
+    }
+
+    pub fn is_false(self) -> Cond {
+        self.is(IsKind::False, false)
+    }
+
+    pub fn abs(self) -> Operand {
+        Operand(Expr::Unary {
+            op: UnaryOp::Abs,
+            expr: Box::new(self.0),
+        })
+    }
+
+    pub fn into_expr(self) -> Expr {
+        self.0
+    }
+
+    fn compare(self, op: OpKind, rhs: impl Into<Operand>) -> Cond {
+        Cond::predicate(Node::BinOp(BinOp {
+            lhs: self.0,
+            op,
+            rhs: rhs.into().0,
+        }))
+    }
+
+    fn approx_eq(self, rhs: impl Into<Operand>, tolerance: Option<Expr>) -> Cond {
+        Cond::predicate(Node::ApproxEq(ApproxEq {
+            lhs: self.0,
+            rhs: rhs.into().0,
+            tolerance,
+        }))
+    }
+
+    fn matching(self, pattern: Pattern) -> Cond {
+        Cond::predicate(Node::Match(Match {
+            expr: self.0,
+            pattern,
+        }))
+    }
+
+    fn is(self, kind: IsKind, negated: bool) -> Cond {
+        Cond::predicate(Node::Is(Is {
+            expr: self.0,
+            negated,
+            kind,
+        }))
+    }
+
+    fn binary(self, op: ArithOp, rhs: impl Into<Operand>) -> Operand {
+        Operand(Expr::Binary {
+            lhs: Box::new(self.0),
+            op,
+            rhs: Box::new(rhs.into().0),
+        })
+    }
+}
+
+impl From<Expr> for Operand {
+    fn from(expr: Expr) -> Self {
+        Operand(expr)
+    }
+}
+
+macro_rules! integer_operands {
+    ($($int:ty),*) => {$(
+        impl From<$int> for Operand {
+            fn from(int: $int) -> Self {
+                Operand(Expr::Literal(Literal::Integer(int.to_string())))
+            }
+        }
+    )*};
+}
+
+integer_operands!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl From<f64> for Operand {
+    /// Floats keep a fraction or exponent, as the lexer writes them. NaN
+    /// has no literal, so `Query::from_tree` rejects conditions with it;
+    /// test for it with `is_nan` instead.
+    fn from(float: f64) -> Self {
+        Operand(Expr::Literal(Literal::float(float)))
+    }
+}
+
+impl From<f32> for Operand {
+    fn from(float: f32) -> Self {
+        Operand::from(float as f64)
+    }
+}
+
+impl From<bool> for Operand {
+    fn from(b: bool) -> Self {
+        Operand(Expr::Literal(Literal::Bool(b)))
+    }
+}
+
+impl From<&str> for Operand {
+    fn from(text: &str) -> Self {
+        Operand(Expr::Literal(Literal::String(text.to_string())))
+    }
+}
+
+impl From<String> for Operand {
+    fn from(text: String) -> Self {
+        Operand(Expr::Literal(Literal::String(text)))
+    }
+}
+
+macro_rules! arith_operators {
+    ($($trait:ident $method:ident $op:ident),*) => {$(
+        impl<T: Into<Operand>> $trait<T> for Operand {
+            type Output = Operand;
+
+            fn $method(self, rhs: T) -> Operand {
+                self.binary(ArithOp::$op, rhs)
+            }
+        }
+    )*};
+}
+
+arith_operators!(Add add Add, Sub sub Sub, Mul mul Mul, Div div Div, Rem rem Rem);
+
+impl Neg for Operand {
+    type Output = Operand;
+
+    fn neg(self) -> Operand {
+        Operand(Expr::Unary {
+            op: UnaryOp::Neg,
+            expr: Box::new(self.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr_ast::parser::Parser;
+
+    fn parse(condition: &str) -> Vec<Node> {
+        Parser::new().parse_condition(condition).unwrap().root
+    }
+
+    #[test]
+    fn build() {
+        let cond = Cond::component(0)
+            .ge(1)
+            .and(Cond::component(1).lt(5))
+            .and(Cond::name("price").le(9.5));
+
+        assert_eq!(
+            cond.clone().into_tree().root,
+            parse(">= 1, < 5, price <= 9.5")
+        );
+        assert_eq!(cond.to_string(), "$0 >= 1, $1 < 5, price <= 9.5");
+
+        assert_eq!(
+            (Cond::component(0) + Cond::component(1) * 2)
+                .abs()
+                .gt(-Cond::path(&[2, 0]) % 3)
+                .into_tree()
+                .root,
+            parse("abs($0 + $1 * 2) > -$2.0 % 3")
+        );
+        assert_eq!(
+            ((Cond::component(0) - 1) - (Cond::value(2) - 3))
+                .ne_to("a")
+                .into_tree()
+                .root,
+            parse("$0 - 1 - (2 - 3) <> 'a'")
+        );
+    }
+
+    #[test]
+    fn build_predicates() {
+        let cond = Cond::component(0)
+            .approx_within(1e10, 0.5)
+            .and(Cond::component(1).is_nan())
+            .and(Cond::component(2).like("a%"))
+            .and(Cond::component(3).glob("b*"))
+            .and(Cond::component(4).is_not_null())
+            .and(Cond::component(5).is_true())
+            .and(Cond::component(6).eq_to(Cond::tuple([1, 2])))
+            .and(Cond::component(7).lt(Cond::datetime("2024-01-05") + Cond::duration("P1M")));
+
+        assert_eq!(
+            cond.into_tree().root,
+            parse(
+                "~= 10000000000.0 ± 0.5, is nan, like 'a%', glob 'b*', \
+                 is not null, is true, = (1, 2), < 2024-01-05 + P1M"
+            )
+        );
+    }
+
+    #[test]
+    fn build_floats() {
+        let cond = Cond::component(0)
+            .lt(f64::INFINITY)
+            .and(Cond::component(1).gt(1e300))
+            .and(Cond::component(2).ne_to(-0.5f32));
+
+        assert_eq!(cond.to_string(), "$0 < 1e999, $1 > 1e300, $2 <> -0.5");
+        assert_eq!(cond.clone().into_tree().root, parse(&cond.to_string()));
+        assert!(Cond::component(0) == Cond::component(0));
+        assert!(Cond::value(1.5) != Cond::value(2.5));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn build_regex() {
+        assert_eq!(
+            Cond::component(0).regex("^a/b$").unwrap().into_tree().root,
+            parse(r"~ /^a\/b$/")
+        );
+        assert!(Cond::component(0).regex("(").is_err());
+    }
+}
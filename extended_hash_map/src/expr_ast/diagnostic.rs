@@ -0,0 +1,149 @@
+use std::fmt;
+
+use super::token::Span;
+
+/// A parse error located in its source, rendered with a caret under the
+/// offending text.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub source: String,
+    pub span: Span,
+    pub expected: String,
+    pub found: String,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    /// Describes `span` of `source` as what was found there.
+    pub(crate) fn new(source: &str, span: Span, expected: &str) -> Self {
+        let found = match source.get(span.start..span.end) {
+            Some(text) if !text.is_empty() => format!("`{}`", text),
+            _ if span.start >= source.len() => "end of input".to_string(),
+            _ => "nothing".to_string(),
+        };
+
+        Self {
+            source: source.to_string(),
+            span,
+            expected: expected.to_string(),
+            found,
+            help: None,
+        }
+    }
+
+    pub(crate) fn found(mut self, found: &str) -> Self {
+        self.found = found.to_string();
+        self
+    }
+
+    pub(crate) fn help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = floor_boundary(&self.source, self.span.start);
+        let line_start = self.source[..start].rfind('\n').map_or(0, |at| at + 1);
+        let line_end = self.source[start..]
+            .find('\n')
+            .map_or(self.source.len(), |at| start + at);
+        let end = floor_boundary(&self.source, self.span.end.clamp(start, line_end));
+
+        let line = &self.source[line_start..line_end];
+        let pad = self.source[line_start..start].chars().count();
+        let width = self.source[start..end].chars().count().max(1);
+
+        writeln!(f, "error: expected {}, found {}", self.expected, self.found)?;
+        writeln!(f, "  | {}", line)?;
+        write!(f, "  | {}{}", " ".repeat(pad), "^".repeat(width))?;
+
+        if let Some(ref help) = self.help {
+            write!(f, "\n  = help: {}", help)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The last char boundary of `text` at or before `at`, so that a span
+/// set by hand never splits a char.
+fn floor_boundary(text: &str, at: usize) -> usize {
+    (0..=at.min(text.len()))
+        .rev()
+        .find(|&at| text.is_char_boundary(at))
+        .unwrap_or(0)
+}
+
+/// The keyword closest to `word`, if it looks like a typo of one.
+pub(crate) fn closest<'k>(word: &str, keywords: &[&'k str]) -> Option<&'k str> {
+    let word = word.to_ascii_lowercase();
+
+    keywords
+        .iter()
+        .map(|&keyword| (distance(&word, keyword), keyword))
+        .filter(|&(distance, _)| distance <= 2 && distance < word.chars().count())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, keyword)| keyword)
+}
+
+/// The Levenshtein distance between two words.
+fn distance(lhs: &str, rhs: &str) -> usize {
+    let rhs = rhs.chars().collect::<Vec<_>>();
+    let mut row = (0..=rhs.len()).collect::<Vec<_>>();
+
+    for (i, lhs_char) in lhs.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &rhs_char) in rhs.iter().enumerate() {
+            let substitution = diagonal + (lhs_char != rhs_char) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[rhs.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let diagnostic = Diagnostic::new("$0 >= 1, x lik 'a'", Span::new(11, 14), "a comparison")
+            .help("did you mean `like`?");
+
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: expected a comparison, found `lik`\n  \
+             | $0 >= 1, x lik 'a'\n  \
+             |            ^^^\n  \
+             = help: did you mean `like`?"
+        );
+        assert_eq!(
+            Diagnostic::new("é >", Span::new(4, 4), "a value").to_string(),
+            "error: expected a value, found end of input\n  | é >\n  |    ^"
+        );
+
+        let mut diagnostic = Diagnostic::new("aé, ü", Span::new(0, 1), "a value");
+        diagnostic.span = Span::new(2, 6);
+
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: expected a value, found `a`\n  | aé, ü\n  |  ^^^"
+        );
+    }
+
+    #[test]
+    fn suggest() {
+        let keywords = ["like", "glob", "is"];
+
+        assert_eq!(closest("lik", &keywords), Some("like"));
+        assert_eq!(closest("GLOV", &keywords), Some("glob"));
+        assert_eq!(closest("y", &keywords), None);
+        assert_eq!(closest("between", &keywords), None);
+    }
+}
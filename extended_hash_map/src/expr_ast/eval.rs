@@ -0,0 +1,515 @@
+use super::{
+    node::{ApproxEq, BinOp, Component, Expr, Is, IsKind, Match, Node, Tree, UnaryOp},
+    time::{self, Duration},
+    token::{
+        literal::Literal,
+        op::{ArithOp, OpKind},
+    },
+};
+
+use std::cmp::Ordering;
+
+/// A value computed from a key or condition. Integers stay exact, compare
+/// exactly against floats and are only promoted to floats by arithmetic with
+/// a float or on overflow. Strings and booleans only compare with their own
+/// kind, and a null propagates through arithmetic but compares with nothing.
+/// Times are UTC nanoseconds since the epoch and tuples compare item by
+/// item.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Integer(i128),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    Time(i128),
+    Duration(Duration),
+    Tuple(Vec<Value>),
+    Null,
+}
+
+impl Value {
+    pub fn from_literal(lit: &Literal) -> Option<Self> {
+        match lit {
+            Literal::Integer(s) => s.parse().ok().map(Value::Integer),
+            Literal::Float(s) => s.parse().ok().map(Value::Float),
+            Literal::String(s) => Some(Value::String(s.clone())),
+            Literal::Bool(b) => Some(Value::Bool(*b)),
+            Literal::Null => Some(Value::Null),
+            Literal::NaN => Some(Value::Float(f64::NAN)),
+            Literal::DateTime(s) => time::parse_datetime(s).map(Value::Time),
+            Literal::Duration(s) => Duration::parse(s).map(Value::Duration),
+        }
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        match node {
+            Node::Literal(lit) => Self::from_literal(lit),
+            Node::Tuple(components) => components
+                .iter()
+                .map(Self::from_node)
+                .collect::<Option<_>>()
+                .map(Value::Tuple),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Integer(int) => Some(int as f64),
+            Value::Float(float) => Some(float),
+            _ => None,
+        }
+    }
+
+    fn neg(self) -> Option<Self> {
+        match self {
+            Value::Integer(int) => Some(
+                int.checked_neg()
+                    .map_or(Value::Float(-(int as f64)), Value::Integer),
+            ),
+            Value::Float(float) => Some(Value::Float(-float)),
+            Value::Duration(duration) => duration.checked_neg().map(Value::Duration),
+            Value::Null => Some(Value::Null),
+            _ => None,
+        }
+    }
+
+    fn abs(self) -> Option<Self> {
+        match self {
+            Value::Integer(int) => Some(
+                int.checked_abs()
+                    .map_or(Value::Float((int as f64).abs()), Value::Integer),
+            ),
+            Value::Float(float) => Some(Value::Float(float.abs())),
+            Value::Duration(duration) if duration.months < 0 || duration.nanos < 0 => {
+                duration.checked_neg().map(Value::Duration)
+            }
+            Value::Duration(_) | Value::Null => Some(self),
+            _ => None,
+        }
+    }
+
+    /// Applies `op`, yielding `None` for non-numeric operands or a division
+    /// by zero. Times and durations only support `time - time`,
+    /// `time ± duration`, `duration ± duration` and `duration * integer`.
+    fn arith(self, op: ArithOp, rhs: Self) -> Option<Self> {
+        if matches!(self, Value::Null) || matches!(rhs, Value::Null) {
+            return Some(Value::Null);
+        }
+
+        match (op, &self, &rhs) {
+            (ArithOp::Sub, Value::Time(lhs), Value::Time(rhs)) => {
+                return Some(Value::Duration(Duration {
+                    months: 0,
+                    nanos: lhs.checked_sub(*rhs)?,
+                }));
+            }
+            (ArithOp::Add, Value::Time(time), Value::Duration(duration))
+            | (ArithOp::Add, Value::Duration(duration), Value::Time(time)) => {
+                return time::shift(*time, *duration).map(Value::Time);
+            }
+            (ArithOp::Sub, Value::Time(time), Value::Duration(duration)) => {
+                return time::shift(*time, duration.checked_neg()?).map(Value::Time);
+            }
+            (ArithOp::Add, Value::Duration(lhs), Value::Duration(rhs)) => {
+                return lhs.checked_add(*rhs).map(Value::Duration);
+            }
+            (ArithOp::Sub, Value::Duration(lhs), Value::Duration(rhs)) => {
+                return lhs.checked_add(rhs.checked_neg()?).map(Value::Duration);
+            }
+            (ArithOp::Mul, Value::Duration(duration), Value::Integer(factor))
+            | (ArithOp::Mul, Value::Integer(factor), Value::Duration(duration)) => {
+                return duration.checked_mul(*factor).map(Value::Duration);
+            }
+            _ => {}
+        }
+
+        if let (Value::Integer(lhs), Value::Integer(rhs)) = (&self, &rhs) {
+            let exact = match op {
+                ArithOp::Add => lhs.checked_add(*rhs),
+                ArithOp::Sub => lhs.checked_sub(*rhs),
+                ArithOp::Mul => lhs.checked_mul(*rhs),
+                ArithOp::Div if lhs.checked_rem(*rhs) == Some(0) => lhs.checked_div(*rhs),
+                ArithOp::Div => None,
+                ArithOp::Rem => lhs.checked_rem(*rhs),
+            };
+
+            if let Some(int) = exact {
+                return Some(Value::Integer(int));
+            }
+        }
+
+        let (lhs, rhs) = (self.as_f64()?, rhs.as_f64()?);
+
+        Some(Value::Float(match op {
+            ArithOp::Div | ArithOp::Rem if rhs == 0.0 => return None,
+            ArithOp::Add => lhs + rhs,
+            ArithOp::Sub => lhs - rhs,
+            ArithOp::Mul => lhs * rhs,
+            ArithOp::Div => lhs / rhs,
+            ArithOp::Rem => lhs % rhs,
+        }))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Integer(lhs), Value::Integer(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::Integer(lhs), Value::Float(rhs)) => cmp_int_float(*lhs, *rhs),
+            (Value::Float(lhs), Value::Integer(rhs)) => {
+                cmp_int_float(*rhs, *lhs).map(Ordering::reverse)
+            }
+            (Value::Float(lhs), Value::Float(rhs)) => lhs.partial_cmp(rhs),
+            (Value::String(lhs), Value::String(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::Bool(lhs), Value::Bool(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::Time(lhs), Value::Time(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::Tuple(lhs), Value::Tuple(rhs)) => {
+                for (lhs, rhs) in lhs.iter().zip(rhs) {
+                    match lhs.partial_cmp(rhs)? {
+                        Ordering::Equal => continue,
+                        ordering => return Some(ordering),
+                    }
+                }
+                Some(lhs.len().cmp(&rhs.len()))
+            }
+            // months have no fixed length, so only order durations whose
+            // calendar and exact parts agree
+            (Value::Duration(lhs), Value::Duration(rhs)) => {
+                match (lhs.months.cmp(&rhs.months), lhs.nanos.cmp(&rhs.nanos)) {
+                    (months, Ordering::Equal) => Some(months),
+                    (Ordering::Equal, nanos) => Some(nanos),
+                    (months, nanos) if months == nanos => Some(months),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Compares an integer with a float without rounding the integer.
+fn cmp_int_float(int: i128, float: f64) -> Option<Ordering> {
+    const BOUND: f64 = i128::MAX as f64;
+
+    if float.is_nan() {
+        return None;
+    } else if float >= BOUND {
+        return Some(Ordering::Less);
+    } else if float < -BOUND {
+        return Some(Ordering::Greater);
+    }
+
+    let trunc = float.trunc();
+
+    Some(
+        int.cmp(&(trunc as i128))
+            .then_with(|| 0.0.partial_cmp(&(float - trunc)).unwrap_or(Ordering::Equal)),
+    )
+}
+
+/// Evaluates conditions against the components of a single parsed key.
+#[derive(Debug)]
+pub struct Scope<'a> {
+    key: &'a Tree,
+    schema: &'a [String],
+    tolerance: f64,
+}
+
+impl<'a> Scope<'a> {
+    pub fn new(key: &'a Tree, schema: &'a [String], tolerance: f64) -> Self {
+        Self {
+            key,
+            schema,
+            tolerance,
+        }
+    }
+
+    /// Whether every predicate of the condition `tree` holds for the key.
+    #[cfg(test)]
+    pub fn holds(&self, tree: &Tree) -> bool {
+        tree.root.iter().all(|node| self.predicate(node))
+    }
+
+    /// Whether a single predicate holds for the key.
+    pub fn predicate(&self, node: &Node) -> bool {
+        match node {
+            Node::BinOp(bin_op) => self.bin_op(bin_op),
+            Node::ApproxEq(approx_eq) => self.approx_eq(approx_eq),
+            Node::Match(Match { expr, pattern }) => match self.eval(expr) {
+                Some(Value::String(text)) => pattern.is_match(&text),
+                _ => false,
+            },
+            Node::Is(is) => self.is(is),
+            _ => false,
+        }
+    }
+
+    fn is(&self, is: &Is) -> bool {
+        let Is {
+            expr,
+            negated,
+            kind,
+        } = is;
+
+        self.eval(expr).is_some_and(|value| {
+            let is = match kind {
+                IsKind::Null => matches!(value, Value::Null),
+                IsKind::NaN => matches!(value, Value::Float(float) if float.is_nan()),
+                IsKind::True => matches!(value, Value::Bool(true)),
+                IsKind::False => matches!(value, Value::Bool(false)),
+            };
+
+            is != *negated
+        })
+    }
+
+    fn approx_eq(&self, approx_eq: &ApproxEq) -> bool {
+        let ApproxEq {
+            lhs,
+            rhs,
+            tolerance,
+        } = approx_eq;
+        let tolerance = match tolerance {
+            Some(tolerance) => self.eval(tolerance).and_then(|value| value.as_f64()),
+            None => Some(self.tolerance),
+        };
+
+        match (self.eval(lhs), self.eval(rhs), tolerance) {
+            (Some(lhs), Some(rhs), Some(tolerance)) => lhs
+                .arith(ArithOp::Sub, rhs)
+                .and_then(|diff| diff.as_f64())
+                .is_some_and(|diff| diff.abs() <= tolerance),
+            _ => false,
+        }
+    }
+
+    fn bin_op(&self, bin_op: &BinOp) -> bool {
+        let BinOp { lhs, op, rhs } = bin_op;
+        let (lhs, rhs) = match (self.eval(lhs), self.eval(rhs)) {
+            (Some(Value::Null), _) | (_, Some(Value::Null)) => return false,
+            (Some(lhs), Some(rhs)) => (lhs, rhs),
+            _ => return false,
+        };
+
+        match op {
+            OpKind::Eq => lhs == rhs,
+            OpKind::Ne => lhs != rhs,
+            OpKind::Ge => lhs >= rhs,
+            OpKind::Gt => lhs > rhs,
+            OpKind::Le => lhs <= rhs,
+            OpKind::Lt => lhs < rhs,
+        }
+    }
+
+    /// Evaluates `expr`, yielding `None` if it refers to a missing component,
+    /// divides by zero or applies arithmetic to a string.
+    pub fn eval(&self, expr: &Expr) -> Option<Value> {
+        match expr {
+            Expr::Literal(lit) => Value::from_literal(lit),
+            Expr::Component(component) => Value::from_node(self.component(component)?),
+            Expr::Tuple(items) => items
+                .iter()
+                .map(|item| self.eval(item))
+                .collect::<Option<_>>()
+                .map(Value::Tuple),
+            Expr::Unary { op, expr } => {
+                let value = self.eval(expr)?;
+
+                match op {
+                    UnaryOp::Neg => value.neg(),
+                    UnaryOp::Abs => value.abs(),
+                }
+            }
+            Expr::Binary { lhs, op, rhs } => self.eval(lhs)?.arith(*op, self.eval(rhs)?),
+        }
+    }
+
+    fn component(&self, component: &Component) -> Option<&'a Node> {
+        let (position, path) = match component {
+            Component::Position(position) => (*position, &[][..]),
+            Component::Path(path) => (*path.first()?, &path[1..]),
+            Component::Name(name) => (self.schema.iter().position(|field| field == name)?, &[][..]),
+        };
+
+        path.iter()
+            .try_fold(self.key.root.get(position)?, |node, &index| match node {
+                Node::Tuple(components) => components.get(index),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr_ast::{LexerErr, Parser, ParserErr};
+
+    fn eval(key: &str, expr: &str) -> Option<Value> {
+        let mut parser = Parser::new();
+        let key = parser.parse(key).unwrap();
+        let condition = parser.parse_condition(&format!("{} = 0", expr)).unwrap();
+        let schema = ["x".to_string(), "y".to_string()];
+
+        match &condition.root[0] {
+            Node::BinOp(bin_op) => Scope::new(&key, &schema, 0.0).eval(&bin_op.lhs),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("2, 3", "x * 2 + 1"), Some(Value::Integer(5)));
+        assert_eq!(eval("2, 3", "x * (2 + y)"), Some(Value::Integer(10)));
+        assert_eq!(eval("2, 3", "-x - -y"), Some(Value::Integer(1)));
+        assert_eq!(eval("2, 3", "y - x - 1"), Some(Value::Integer(0)));
+        assert_eq!(eval("2, 3", "abs($0 - $1) % 2"), Some(Value::Integer(1)));
+        assert_eq!(eval("7, 2", "x / y"), Some(Value::Float(3.5)));
+        assert_eq!(eval("7, 0", "x / y"), None);
+        assert_eq!(eval("7, 0", "z"), None);
+    }
+
+    #[test]
+    fn exact_integers() {
+        let big = "9007199254740993";
+
+        assert!(matches!(
+            eval(big, "x"),
+            Some(Value::Integer(9007199254740993))
+        ));
+        assert!(eval(big, "x") != Some(Value::Integer(9007199254740992)));
+        assert!(eval(big, "x") > Some(Value::Integer(9007199254740992)));
+        assert!(matches!(eval("6, 3", "x / y"), Some(Value::Integer(2))));
+        assert!(matches!(eval("2, 0.5", "x * y"), Some(Value::Float(_))));
+        assert_eq!(eval("2, 0.5", "x * y"), Some(Value::Integer(1)));
+        assert!(eval("3, 2.5", "x") > eval("3, 2.5", "y"));
+        assert!(eval("-3, -2.5", "x") < eval("-3, -2.5", "y"));
+        assert!(
+            eval("9007199254740993, 9007199254740993.0", "x")
+                > eval("9007199254740993, 9007199254740993.0", "y")
+        );
+        assert!(matches!(
+            eval("170141183460469231731687303715884105727, 1", "x + y"),
+            Some(Value::Float(_))
+        ));
+        assert_eq!(
+            Parser::new()
+                .parse("340282366920938463463374607431768211456")
+                .unwrap_err(),
+            ParserErr::Lexer(LexerErr::InvalidNumber(0))
+        );
+        assert_eq!(
+            Value::from_literal(&Literal::Integer(
+                "170141183460469231731687303715884105728".to_string()
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn holds() {
+        let mut parser = Parser::new();
+        let key = parser.parse("(1, 5, 3)").unwrap();
+        let scope = Scope::new(&key, &[], 0.0);
+
+        assert!(scope.holds(&parser.parse_condition("<5, >=5, >=3").unwrap()));
+        assert!(!scope.holds(&parser.parse_condition("<5, >=5, >3").unwrap()));
+        assert!(scope.holds(&parser.parse_condition("$1 - $0 > $2").unwrap()));
+    }
+
+    #[test]
+    fn null_and_nan() {
+        let mut parser = Parser::new();
+        let key = parser.parse("1, , NaN").unwrap();
+        let scope = Scope::new(&key, &[], 0.0);
+        let holds =
+            |condition: &str| scope.holds(&Parser::new().parse_condition(condition).unwrap());
+
+        assert!(holds("is not null, is null, is nan"));
+        assert!(holds("$1 + 1 is null"));
+        assert!(!holds("$0 is nan"));
+        assert!(!holds("$1 = 1"));
+        assert!(!holds("$1 <> 1"));
+        assert!(!holds("$2 = $2"));
+        assert!(holds("$2 <> $2"));
+        assert!(!holds("$3 is not null"));
+    }
+
+    #[test]
+    fn booleans() {
+        let mut parser = Parser::new();
+        let key = parser.parse("1, true, false").unwrap();
+        let scope = Scope::new(&key, &[], 0.0);
+        let holds =
+            |condition: &str| scope.holds(&Parser::new().parse_condition(condition).unwrap());
+
+        assert!(holds("$1 = true, $2 is false"));
+        assert!(holds("$1 is not false, $2 < $1"));
+        assert!(!holds("$0 = true"));
+        assert!(!holds("$1 = 1"));
+        assert!(!holds("$1 + 1 = 2"));
+        assert!(!holds("$0 is true"));
+    }
+
+    #[test]
+    fn times() {
+        let mut parser = Parser::new();
+        let key = parser
+            .parse("2024-01-05, 2024-01-05T12:00+02:00, P1M")
+            .unwrap();
+        let scope = Scope::new(&key, &[], 0.0);
+        let holds =
+            |condition: &str| scope.holds(&Parser::new().parse_condition(condition).unwrap());
+
+        assert!(holds(
+            "> 2024-01-01, < 2024-01-05T12:00, = P30D + P1M - P30D"
+        ));
+        assert!(holds("$1 - $0 = PT10H, abs($0 - $1) < P1D"));
+        assert!(holds("$0 + $2 = 2024-02-05, $0 - P1D * 5 = 2023-12-31"));
+        assert!(holds("2024-01-31 + $2 = 2024-02-29"));
+        assert!(!holds("$2 < P31D"));
+        assert!(!holds("$2 >= P28D"));
+        assert!(!holds("$0 = 20240105"));
+        assert!(!holds("$0 * 2 is not null"));
+    }
+
+    #[test]
+    fn tuples() {
+        let mut parser = Parser::new();
+        let key = parser.parse("((1, 2), (eu, (3, 4)), 5)").unwrap();
+        let scope = Scope::new(&key, &[], 0.0);
+        let holds =
+            |condition: &str| scope.holds(&Parser::new().parse_condition(condition).unwrap());
+
+        assert!(holds("$0.1 = 2, $1.1.0 + $1.1.1 = 7, $1.0 = 'eu'"));
+        assert!(holds("= (1, 2), $1 = ('eu', (3, 4.0))"));
+        assert!(holds("$0 > (1, 1), $0 < (1, 2, 0), $0 >= (1,)"));
+        assert!(!holds("$0 = (1, 2, 3)"));
+        assert!(!holds("$0 > (1, 'a')"));
+        assert!(!holds("$2.0 is not null"));
+        assert!(!holds("$0.2 is not null"));
+    }
+
+    #[test]
+    fn approx_eq() {
+        let mut parser = Parser::new();
+        let key = parser.parse("0.1, 0.2").unwrap();
+        let holds = |condition: &str, tolerance: f64| {
+            let tree = Parser::new().parse_condition(condition).unwrap();
+            Scope::new(&key, &[], tolerance).holds(&tree)
+        };
+
+        assert!(!holds("$0 + $1 = 0.3", 1e-9));
+        assert!(holds("$0 + $1 ~= 0.3", 1e-9));
+        assert!(!holds("$0 + $1 ~= 0.3", 0.0));
+        assert!(holds("$0 ~= 0.15 ± 0.05", 0.0));
+        assert!(!holds("$0 ~= 0.15 ± 0.01", 1.0));
+        assert!(!holds("$0 ~= 0.1 ± -1", 1.0));
+    }
+}
@@ -0,0 +1,68 @@
+/// The grammar of keys: which chars separate components, which bracket
+/// pairs may wrap them and whether anything else is an error.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct KeyFormat {
+    pub(crate) delimiters: Option<String>,
+    pub(crate) brackets: Vec<(char, char)>,
+    pub(crate) strict: bool,
+}
+
+impl KeyFormat {
+    /// The default format: any run of chars that start no literal separates
+    /// components and parentheses are skipped, balanced or not.
+    pub fn lenient() -> Self {
+        Default::default()
+    }
+
+    /// Only `delimiters` separate components; any other char outside a
+    /// literal, components without a delimiter between them or an
+    /// unbalanced bracket fail to parse.
+    pub fn strict(delimiters: &str) -> Self {
+        Self {
+            strict: true,
+            ..Self::lenient().with_delimiters(delimiters)
+        }
+    }
+
+    /// Restricts delimiters to the given chars, each of which separates
+    /// components on its own. Leniently, other chars are part of the
+    /// component they appear in, which then reads as a string.
+    pub fn with_delimiters(mut self, delimiters: &str) -> Self {
+        self.delimiters = Some(delimiters.to_string());
+        self
+    }
+
+    pub fn with_brackets(mut self, brackets: &[(char, char)]) -> Self {
+        self.brackets = brackets.to_vec();
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Whether components must be separated by an explicit delimiter.
+    pub(crate) fn requires_delimiter(&self) -> bool {
+        self.strict && self.delimiters.is_some()
+    }
+
+    pub(crate) fn closer(&self, open: char) -> Option<char> {
+        self.brackets
+            .iter()
+            .find_map(|&(lhs, rhs)| (lhs == open).then_some(rhs))
+    }
+
+    pub(crate) fn is_closer(&self, ch: char) -> bool {
+        self.brackets.iter().any(|&(_, rhs)| rhs == ch)
+    }
+}
+
+impl Default for KeyFormat {
+    fn default() -> Self {
+        Self {
+            delimiters: None,
+            brackets: vec![('(', ')')],
+            strict: false,
+        }
+    }
+}
@@ -1,78 +1,588 @@
-use super::token::{literal::Literal, op::OpKind, Token};
-use std::{iter::Peekable, str::Chars};
+use std::{error::Error, fmt};
+
+use super::{
+    format::KeyFormat,
+    time::{self, Duration},
+    token::{
+        literal::Literal,
+        op::{ArithOp, OpKind},
+        Span, Token,
+    },
+};
 
 fn is_ignore_char(ch: char) -> bool {
-    ch.is_whitespace() || "()".contains(ch)
+    ch.is_whitespace()
+}
+
+fn is_ident_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+fn is_ident_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Whether `text` lexes as a single identifier.
+pub(crate) fn is_ident(text: &str) -> bool {
+    let mut chars = text.chars();
+
+    chars.next().is_some_and(is_ident_start) && chars.all(is_ident_char)
+}
+
+fn is_delim_char(ch: char, mode: Mode) -> bool {
+    !(is_ignore_char(ch)
+        || ch.is_ascii_digit()
+        || "<>=>".contains(ch)
+        || is_ident_start(ch)
+        || "'\"".contains(ch)
+        || (mode == Mode::Condition && "+-*/%()~±".contains(ch)))
+}
+
+/// A lexing error with the byte offset of the offending token or char.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LexerErr {
+    InvalidNumber(usize),
+    InvalidTime(usize),
+    UnterminatedLiteral(usize),
+    UnexpectedChar(char, usize),
+    UnclosedBracket(usize),
+}
+
+impl LexerErr {
+    /// The byte offset the error was found at.
+    pub fn offset(&self) -> usize {
+        match *self {
+            LexerErr::InvalidNumber(offset)
+            | LexerErr::InvalidTime(offset)
+            | LexerErr::UnterminatedLiteral(offset)
+            | LexerErr::UnexpectedChar(_, offset)
+            | LexerErr::UnclosedBracket(offset) => offset,
+        }
+    }
+}
+
+impl fmt::Display for LexerErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LexerErr::InvalidNumber(offset) => write!(f, "invalid number at byte {}", offset),
+            LexerErr::InvalidTime(offset) => {
+                write!(f, "invalid date or duration at byte {}", offset)
+            }
+            LexerErr::UnterminatedLiteral(offset) => {
+                write!(f, "unterminated literal at byte {}", offset)
+            }
+            LexerErr::UnexpectedChar(ch, offset) => {
+                write!(f, "unexpected char `{}` at byte {}", ch, offset)
+            }
+            LexerErr::UnclosedBracket(offset) => write!(f, "unclosed bracket at byte {}", offset),
+        }
+    }
+}
+
+impl Error for LexerErr {}
+
+/// Iterates the chars of the lexed text, keeping track of the byte offset.
+#[derive(Debug, Clone)]
+struct Cursor<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, offset: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn next_if(&mut self, func: impl FnOnce(&char) -> bool) -> Option<char> {
+        match self.peek() {
+            Some(ch) if func(&ch) => self.next(),
+            _ => None,
+        }
+    }
+
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        self.next_if(|ch| ch == expected)
+    }
 }
 
-#[derive(Debug)]
-pub enum LexerErr {}
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.offset += ch.len_utf8();
+
+        Some(ch)
+    }
+}
 
-#[derive(Debug)]
+/// Selects the grammar the lexer tokenizes: keys treat arithmetic operators
+/// as delimiters and follow a `KeyFormat`, conditions turn them into tokens.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Mode {
+    Key,
+    Condition,
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct Lexer<'a> {
-    current_char: Peekable<Chars<'a>>,
+    current_char: Cursor<'a>,
+    mode: Mode,
+    strict: bool,
+    after_operand: bool,
+    after_tilde: bool,
+    after_path: bool,
+    after_dot: bool,
+    format: KeyFormat,
+    closers: Vec<(char, usize)>,
+    start: usize,
 }
 
 impl<'a> Lexer<'a> {
+    #[cfg(test)]
     pub fn next(&mut self) -> Result<Token, LexerErr> {
-        while let Some(&next_char) = self.current_char.peek() {
+        self.next_spanned().map(|(token, _)| token)
+    }
+
+    /// Lexes the next token along with the span of text it was lexed from.
+    pub fn next_spanned(&mut self) -> Result<(Token, Span), LexerErr> {
+        let mut token = self.token()?;
+
+        if matches!(token, Token::Literal(_) | Token::Ident(_)) && self.continues_component() {
+            token = self.free_component();
+        }
+        let span = Span::new(self.start, self.offset());
+
+        self.after_operand = matches!(
+            token,
+            Token::Literal(_) | Token::Ident(_) | Token::Position(_) | Token::RParen
+        );
+        self.after_tilde = token == Token::Tilde;
+        self.after_path = matches!(token, Token::Position(_))
+            || (self.after_dot && matches!(token, Token::Literal(Literal::Integer(_))));
+        self.after_dot = token == Token::Dot;
+
+        Ok((token, span))
+    }
+
+    fn token(&mut self) -> Result<Token, LexerErr> {
+        while let Some(next_char) = self.current_char.peek() {
+            self.start = self.offset();
+
             if is_ignore_char(next_char) {
                 self.skip();
+                self.after_path = false;
                 continue;
             }
 
-            if let Some(op) = self.op_kind(next_char) {
+            if self.mode == Mode::Key {
+                if let Some(token) = self.bracket(next_char)? {
+                    return Ok(token);
+                }
+            }
+
+            if self.after_tilde && next_char == '/' {
+                return self.regex();
+            } else if self.after_path && next_char == '.' && self.starts_digit(1) {
+                self.current_char.next();
+                return Ok(Token::Dot);
+            } else if self.after_dot && next_char.is_ascii_digit() {
+                return Ok(Token::Literal(Literal::Integer(self.integer())));
+            } else if self.is_sign(next_char) {
+                return Ok(Token::Literal(self.number()?));
+            } else if let Some(op) = self.op_kind(next_char) {
                 return Ok(Token::Op(op));
-            } else if next_char.is_ascii_digit() {
-                return Ok(Token::Literal(self.number()));
-            } else {
+            } else if let Some(token) = self.punct(next_char) {
+                return Ok(token);
+            } else if let Some(date) = self.datetime() {
+                return Ok(Token::Literal(date));
+            } else if self.starts_number(0) {
+                return Ok(Token::Literal(self.number()?));
+            } else if "'\"".contains(next_char) {
+                return Ok(Token::Literal(self.string()?));
+            } else if self.starts_duration() {
+                return self.duration();
+            } else if is_ident_start(next_char) {
+                return Ok(Token::Ident(self.ident()));
+            } else if self.mode == Mode::Condition && next_char == '$' && self.starts_digit(1) {
+                self.current_char.next();
+                return Ok(Token::Position(
+                    self.integer().parse().unwrap_or(usize::MAX),
+                ));
+            } else if self.is_delim_char(next_char) {
                 self.skip_delim();
                 return Ok(Token::Delim);
+            } else if self.is_strict() {
+                return Err(LexerErr::UnexpectedChar(next_char, self.offset()));
+            } else if self.continues_component() {
+                return Ok(self.free_component());
+            } else {
+                self.current_char.next();
+            }
+        }
+
+        self.start = self.offset();
+
+        match self.closers.last() {
+            Some(&(_, offset)) if self.is_strict() => Err(LexerErr::UnclosedBracket(offset)),
+            _ => Ok(Token::Eof),
+        }
+    }
+
+    /// Keys follow the strictness of their format, conditions that of the
+    /// lexer.
+    fn is_strict(&self) -> bool {
+        match self.mode {
+            Mode::Key => self.format.strict,
+            Mode::Condition => self.strict,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.current_char.offset
+    }
+
+    /// Lexes a key bracket of the format into a parenthesis token. Strictly,
+    /// brackets must nest and close in order.
+    fn bracket(&mut self, ch: char) -> Result<Option<Token>, LexerErr> {
+        if let Some(closer) = self.format.closer(ch) {
+            self.closers.push((closer, self.offset()));
+            self.current_char.next();
+
+            return Ok(Some(Token::LParen));
+        } else if !self.format.is_closer(ch) {
+            return Ok(None);
+        }
+
+        if self.closers.pop().map(|(closer, _)| closer) != Some(ch) && self.format.strict {
+            return Err(LexerErr::UnexpectedChar(ch, self.offset()));
+        }
+        self.current_char.next();
+
+        Ok(Some(Token::RParen))
+    }
+
+    fn is_delim_char(&self, ch: char) -> bool {
+        match (self.mode, &self.format.delimiters) {
+            (Mode::Key, Some(delimiters)) => delimiters.contains(ch),
+            (Mode::Key, None) => is_delim_char(ch, self.mode) && !self.format.is_closer(ch),
+            (Mode::Condition, _) if self.strict => ",;".contains(ch),
+            (Mode::Condition, _) => is_delim_char(ch, self.mode),
+        }
+    }
+
+    /// Whether the next char belongs to the current key component: leniently
+    /// with explicit delimiters, any char that separates nothing does.
+    fn continues_component(&self) -> bool {
+        if self.mode != Mode::Key || self.format.strict || self.format.delimiters.is_none() {
+            return false;
+        }
+
+        match self.current_char.peek() {
+            Some(ch) => {
+                !(is_ignore_char(ch)
+                    || self.is_delim_char(ch)
+                    || self.format.closer(ch).is_some()
+                    || self.format.is_closer(ch))
             }
+            None => false,
+        }
+    }
+
+    /// Lexes the rest of a key component, from its start up to the next
+    /// delimiter, bracket or ignored char, into a string.
+    fn free_component(&mut self) -> Token {
+        while self.continues_component() {
+            self.current_char.next();
+        }
+        let text = &self.current_char.text[self.start..self.offset()];
+
+        Token::Literal(Literal::String(text.to_string()))
+    }
+
+    /// A `+` or `-` directly followed by a number starts a signed number,
+    /// unless it follows an operand, where it is a binary operator or, in
+    /// keys, a delimiter.
+    fn is_sign(&self, ch: char) -> bool {
+        "+-".contains(ch) && !self.after_operand && self.starts_number(1)
+    }
+
+    /// Whether a number starts `n` chars ahead: a digit, or a dot followed
+    /// by a digit.
+    fn starts_number(&self, n: usize) -> bool {
+        match self.peek_nth(n) {
+            Some('.') => self.starts_digit(n + 1),
+            Some(ch) => ch.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    /// Lexes a number into a normalized literal: separators are dropped,
+    /// `0x`/`0o`/`0b` integers are converted to decimal and a leading dot
+    /// gets a zero integer part.
+    fn number(&mut self) -> Result<Literal, LexerErr> {
+        let start = self.offset();
+        let mut number = match self.current_char.next_if(|&ch| "+-".contains(ch)) {
+            Some('-') => String::from("-"),
+            _ => String::new(),
+        };
+
+        if let Some(radix) = self.radix() {
+            number += &self.digits(radix);
+
+            return match i128::from_str_radix(&number, radix) {
+                Ok(int) => Ok(Literal::Integer(int.to_string())),
+                Err(_) => Err(LexerErr::InvalidNumber(start)),
+            };
         }
 
-        Ok(Token::Eof)
+        let int_part = self.integer();
+        number += if int_part.is_empty() { "0" } else { &int_part };
+
+        let mut is_float = false;
+
+        if let Some(dot) = self.current_char.next_if_eq(&'.') {
+            is_float = true;
+            number.push(dot);
+            number += &self.integer();
+        }
+
+        if self.is_exponent() {
+            is_float = true;
+            number.extend(self.current_char.next());
+            number.extend(self.current_char.next_if(|&ch| "+-".contains(ch)));
+            number += &self.integer();
+        }
+
+        if is_float {
+            Ok(Literal::Float(number))
+        } else if number.parse::<i128>().is_ok() {
+            Ok(Literal::Integer(number))
+        } else {
+            Err(LexerErr::InvalidNumber(start))
+        }
+    }
+
+    /// Whether a `YYYY-MM-DD` date starts here.
+    fn starts_date(&self) -> bool {
+        let mut chars = self.current_char.clone();
+
+        (0..10).all(|i| match chars.next() {
+            Some(ch) if i == 4 || i == 7 => ch == '-',
+            Some(ch) => ch.is_ascii_digit(),
+            None => false,
+        })
     }
 
-    fn number(&mut self) -> Literal {
-        let mut int_part = self.integer();
+    /// Lexes a date with an optional `T` time and UTC offset, if a valid
+    /// one starts here. Anything else shaped like a date, such as
+    /// `2024-13-05`, is left to lex as numbers.
+    fn datetime(&mut self) -> Option<Literal> {
+        if !self.starts_date() {
+            return None;
+        }
+
+        let checkpoint = self.current_char.clone();
+        let mut text = (0..10)
+            .filter_map(|_| self.current_char.next())
+            .collect::<String>();
 
-        match self.current_char.next_if_eq(&'.') {
-            Some(dot) => {
-                int_part.push(dot);
-                Literal::Float(int_part + &self.integer())
+        if self.peek_nth(0) == Some('T') && self.starts_digit(1) {
+            while let Some(ch) = self
+                .current_char
+                .next_if(|&ch| ch.is_ascii_digit() || "T:.".contains(ch))
+            {
+                text.push(ch);
             }
-            None => Literal::Integer(int_part),
+
+            if let Some(z) = self.current_char.next_if_eq(&'Z') {
+                text.push(z);
+            } else if self.peek_nth(0).is_some_and(|ch| "+-".contains(ch))
+                && self.starts_digit(1)
+                && self.starts_digit(2)
+            {
+                text.extend(self.current_char.next());
+                while let Some(ch) = self
+                    .current_char
+                    .next_if(|&ch| ch.is_ascii_digit() || ch == ':')
+                {
+                    text.push(ch);
+                }
+            }
+        }
+
+        if time::parse_datetime(&text).is_none() {
+            self.current_char = checkpoint;
+            return None;
+        }
+
+        Some(Literal::DateTime(text))
+    }
+
+    fn starts_duration(&self) -> bool {
+        self.peek_nth(0) == Some('P')
+            && (self.starts_digit(1) || (self.peek_nth(1) == Some('T') && self.starts_digit(2)))
+    }
+
+    fn duration(&mut self) -> Result<Token, LexerErr> {
+        let start = self.offset();
+        let mut text = String::new();
+
+        while let Some(ch) = self
+            .current_char
+            .next_if(|&ch| ch.is_ascii_digit() || ".PYMWDTHS".contains(ch))
+        {
+            text.push(ch);
+        }
+
+        match Duration::parse(&text) {
+            Some(_) => Ok(Token::Literal(Literal::Duration(text))),
+            None => Err(LexerErr::InvalidTime(start)),
+        }
+    }
+
+    fn radix(&mut self) -> Option<u32> {
+        let radix = match (self.peek_nth(0), self.peek_nth(1)) {
+            (Some('0'), Some('x' | 'X')) => 16,
+            (Some('0'), Some('o' | 'O')) => 8,
+            (Some('0'), Some('b' | 'B')) => 2,
+            _ => return None,
+        };
+
+        if !self.peek_nth(2).is_some_and(|ch| ch.is_digit(radix)) {
+            return None;
+        }
+
+        self.current_char.nth(1);
+        Some(radix)
+    }
+
+    fn is_exponent(&self) -> bool {
+        match (self.peek_nth(0), self.peek_nth(1)) {
+            (Some('e' | 'E'), Some('+' | '-')) => self.starts_digit(2),
+            (Some('e' | 'E'), _) => self.starts_digit(1),
+            _ => false,
         }
     }
 
+    fn starts_digit(&self, n: usize) -> bool {
+        self.peek_nth(n).is_some_and(|ch| ch.is_ascii_digit())
+    }
+
     fn integer(&mut self) -> String {
-        let mut int = String::new();
+        self.digits(10)
+    }
+
+    /// Lexes digits of the given radix, dropping `_` separators.
+    fn digits(&mut self, radix: u32) -> String {
+        let mut digits = String::new();
+
+        while let Some(ch) = self
+            .current_char
+            .next_if(|&ch| ch.is_digit(radix) || ch == '_')
+        {
+            if ch != '_' {
+                digits.push(ch);
+            }
+        }
+        digits
+    }
+
+    /// Lexes a single or double quoted string, where a backslash escapes
+    /// the next char.
+    fn string(&mut self) -> Result<Literal, LexerErr> {
+        let start = self.offset();
+        let quote = self.current_char.next();
+        let mut string = String::new();
+
+        while let Some(ch) = self.current_char.next() {
+            match ch {
+                '\\' => string.extend(self.current_char.next()),
+                _ if Some(ch) == quote => return Ok(Literal::String(string)),
+                _ => string.push(ch),
+            }
+        }
+
+        Err(LexerErr::UnterminatedLiteral(start))
+    }
+
+    /// Lexes a `/.../` regex after `~`; `\/` stands for a slash, other
+    /// escapes are kept for the regex itself.
+    fn regex(&mut self) -> Result<Token, LexerErr> {
+        let start = self.offset();
+        self.current_char.next();
+
+        let mut regex = String::new();
 
-        while let Some(ch) = self.current_char.next_if(|&ch| ch.is_ascii_digit()) {
-            int.push(ch);
+        while let Some(ch) = self.current_char.next() {
+            match ch {
+                '/' => return Ok(Token::Regex(regex)),
+                '\\' if self.current_char.next_if_eq(&'/').is_some() => regex.push('/'),
+                _ => regex.push(ch),
+            }
         }
-        int
+
+        Err(LexerErr::UnterminatedLiteral(start))
+    }
+
+    fn ident(&mut self) -> String {
+        let mut ident = String::new();
+
+        while let Some(ch) = self.current_char.next_if(|&ch| is_ident_char(ch)) {
+            ident.push(ch);
+        }
+        ident
     }
 
     fn skip(&mut self) {
-        while let Some(_) = self.current_char.next_if(|&ch| is_ignore_char(ch)) {}
+        while self
+            .current_char
+            .next_if(|&ch| is_ignore_char(ch))
+            .is_some()
+        {}
     }
 
+    /// Skips a run of delimiter chars, stopping early where a (signed)
+    /// number starts. Every `,` or `;` begins a run of its own, so `1,,5`
+    /// holds an empty component.
     fn skip_delim(&mut self) {
-        while let Some(_) = self
-            .current_char
-            .next_if(|&ch| !is_ignore_char(ch) && !ch.is_ascii_digit() && !"<>=>".contains(ch))
-        {
+        self.current_char.next();
+
+        if self.mode == Mode::Key && self.format.delimiters.is_some() {
+            return;
+        }
+
+        while let Some(ch) = self.current_char.peek() {
+            if !self.is_delim_char(ch)
+                || ",;".contains(ch)
+                || self.starts_number(0)
+                || ("+-".contains(ch) && self.starts_number(1))
+            {
+                break;
+            }
+            self.current_char.next();
         }
     }
 
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.current_char.clone().nth(n)
+    }
+
     fn op_kind(&mut self, ch: char) -> Option<OpKind> {
         match ch {
             '=' => {
                 self.current_char.next();
+                self.current_char.next_if_eq(&'=');
                 Some(OpKind::Eq)
-            },
+            }
+            '!' if self.peek_nth(1) == Some('=') => {
+                self.current_char.nth(1);
+                Some(OpKind::Ne)
+            }
             '>' => {
                 self.current_char.next();
                 match self.current_char.next_if_eq(&'=') {
@@ -83,23 +593,95 @@ impl<'a> Lexer<'a> {
             '<' => {
                 self.current_char.next();
                 match self.current_char.next_if(|&ch| "=>".contains(ch)) {
-                    Some(ch) => return Some(if ch == '=' { OpKind::Le } else { OpKind::Ne }),
-                    None => return Some(OpKind::Lt),
+                    Some(ch) => Some(if ch == '=' { OpKind::Le } else { OpKind::Ne }),
+                    None => Some(OpKind::Lt),
                 }
             }
             _ => None,
         }
     }
 
+    fn punct(&mut self, ch: char) -> Option<Token> {
+        if self.mode != Mode::Condition {
+            return None;
+        }
+
+        let token = match (ch, self.peek_nth(1)) {
+            ('~', Some('=')) => {
+                self.current_char.nth(1);
+                return Some(Token::Approx);
+            }
+            ('+', Some('/')) if self.peek_nth(2) == Some('-') => {
+                self.current_char.nth(2);
+                return Some(Token::PlusMinus);
+            }
+            ('~', _) => Token::Tilde,
+            ('±', _) => Token::PlusMinus,
+            ('+', _) => Token::Arith(ArithOp::Add),
+            ('-', _) => Token::Arith(ArithOp::Sub),
+            ('*', _) => Token::Arith(ArithOp::Mul),
+            ('/', _) => Token::Arith(ArithOp::Div),
+            ('%', _) => Token::Arith(ArithOp::Rem),
+            ('(', _) => Token::LParen,
+            (')', _) => Token::RParen,
+            _ => return None,
+        };
+
+        self.current_char.next();
+        Some(token)
+    }
+
     pub fn set(&mut self, text: &'a str) {
-        self.current_char = text.chars().peekable();
+        self.current_char = Cursor::new(text);
+        self.after_operand = false;
+        self.after_tilde = false;
+        self.after_path = false;
+        self.after_dot = false;
+        self.closers.clear();
+        self.start = 0;
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Makes conditions reject chars that start no token instead of
+    /// treating them as delimiters; only `,` and `;` delimit then.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn set_format(&mut self, format: KeyFormat) {
+        self.format = format;
+    }
+
+    pub fn format(&self) -> &KeyFormat {
+        &self.format
+    }
+
+    /// Skips a char the lexer failed on, returning false at the end.
+    pub fn skip_char(&mut self) -> bool {
+        self.current_char.next().is_some()
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.current_char.text
     }
 }
 
 impl<'a> From<&'a str> for Lexer<'a> {
     fn from(text: &'a str) -> Self {
         Self {
-            current_char: text.chars().peekable(),
+            current_char: Cursor::new(text),
+            mode: Mode::Key,
+            strict: false,
+            after_operand: false,
+            after_tilde: false,
+            after_path: false,
+            after_dot: false,
+            format: KeyFormat::default(),
+            closers: Vec::new(),
+            start: 0,
         }
     }
 }
@@ -122,8 +704,10 @@ mod tests {
             ],
             vec![
                 vec![
+                    Token::LParen,
                     Token::Op(OpKind::Ge),
                     Token::Literal(Literal::Integer(String::from("4"))),
+                    Token::RParen,
                 ],
                 vec![
                     Token::Op(OpKind::Lt),
@@ -149,22 +733,24 @@ mod tests {
                 vec![
                     Token::Op(OpKind::Lt),
                     Token::Literal(Literal::Integer(String::from("5"))),
-                    Token::Delim,
+                    Token::Ident(String::from("abc")),
                     Token::Op(OpKind::Ge),
                     Token::Literal(Literal::Integer(String::from("5"))),
                     Token::Delim,
+                    Token::Ident(String::from("c")),
                     Token::Op(OpKind::Ge),
                     Token::Literal(Literal::Integer(String::from("3"))),
                 ],
                 vec![
-                    Token::Delim,
+                    Token::Ident(String::from("a")),
                     Token::Op(OpKind::Ge),
                     Token::Literal(Literal::Integer(String::from("2"))),
                 ],
                 vec![
                     Token::Op(OpKind::Gt),
                     Token::Delim,
-                    Token::Literal(Literal::Float(String::from("2.5"))),
+                    Token::Literal(Literal::Float(String::from("0.2"))),
+                    Token::Literal(Literal::Float(String::from("0.5"))),
                 ],
                 vec![
                     Token::Op(OpKind::Lt),
@@ -182,7 +768,7 @@ mod tests {
     fn from() {
         let lexer = Lexer::from(">= 4");
         let lexer_str =
-            "Lexer { current_char: Peekable { iter: Chars(['>', '=', ' ', '4']), peeked: None } }";
+            "Lexer { current_char: Cursor { text: \">= 4\", offset: 0 }, mode: Key, strict: false, after_operand: false, after_tilde: false, after_path: false, after_dot: false, format: KeyFormat { delimiters: None, brackets: [('(', ')')], strict: false }, closers: [], start: 0 }";
 
         assert_eq!(lexer_str, format!("{:?}", lexer));
     }
@@ -200,6 +786,384 @@ mod tests {
         }
     }
 
+    #[test]
+    fn tokenize_idents() {
+        let mut lexer = Lexer::from("y>=5 and x_1 < 10");
+        lexer.set_mode(Mode::Condition);
+
+        for token in [
+            Token::Ident(String::from("y")),
+            Token::Op(OpKind::Ge),
+            Token::Literal(Literal::Integer(String::from("5"))),
+            Token::Ident(String::from("and")),
+            Token::Ident(String::from("x_1")),
+            Token::Op(OpKind::Lt),
+            Token::Literal(Literal::Integer(String::from("10"))),
+            Token::Eof,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+    }
+
+    #[test]
+    fn tokenize_signed() {
+        let int = |s: &str| Token::Literal(Literal::Integer(String::from(s)));
+        let float = |s: &str| Token::Literal(Literal::Float(String::from(s)));
+
+        let mut lexer = Lexer::from("(-3, +5, -2.5) 1-5");
+        for token in [
+            Token::LParen,
+            int("-3"),
+            Token::Delim,
+            int("5"),
+            Token::Delim,
+            float("-2.5"),
+            Token::RParen,
+            int("1"),
+            Token::Delim,
+            int("5"),
+            Token::Eof,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+
+        let mut lexer = Lexer::from("> -1, x-1 * -2 - -y");
+        lexer.set_mode(Mode::Condition);
+        for token in [
+            Token::Op(OpKind::Gt),
+            int("-1"),
+            Token::Delim,
+            Token::Ident(String::from("x")),
+            Token::Arith(ArithOp::Sub),
+            int("1"),
+            Token::Arith(ArithOp::Mul),
+            int("-2"),
+            Token::Arith(ArithOp::Sub),
+            Token::Arith(ArithOp::Sub),
+            Token::Ident(String::from("y")),
+            Token::Eof,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+    }
+
+    #[test]
+    fn tokenize_numbers() {
+        let int = |s: &str| Token::Literal(Literal::Integer(String::from(s)));
+        let float = |s: &str| Token::Literal(Literal::Float(String::from(s)));
+
+        let mut lexer =
+            Lexer::from("1e-3, 2.5E6, 0x1F, -0b101, 0o17, 1_000_000, .5, -.25e+2, 1,-5, 0xfe");
+        for token in [
+            float("1e-3"),
+            Token::Delim,
+            float("2.5E6"),
+            Token::Delim,
+            int("31"),
+            Token::Delim,
+            int("-5"),
+            Token::Delim,
+            int("15"),
+            Token::Delim,
+            int("1000000"),
+            Token::Delim,
+            float("0.5"),
+            Token::Delim,
+            float("-0.25e+2"),
+            Token::Delim,
+            int("1"),
+            Token::Delim,
+            int("-5"),
+            Token::Delim,
+            int("254"),
+            Token::Eof,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+
+        let mut lexer = Lexer::from("1e, 0x");
+        for token in [
+            int("1"),
+            Token::Ident(String::from("e")),
+            Token::Delim,
+            int("0"),
+            Token::Ident(String::from("x")),
+            Token::Eof,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+
+        let mut lexer = Lexer::from("0x1_0000_0000_0000_0000_0000_0000_0000_0000");
+        assert_eq!(lexer.next(), Err(LexerErr::InvalidNumber(0)));
+
+        let mut lexer = Lexer::from("170141183460469231731687303715884105727");
+        assert_eq!(
+            lexer.next().unwrap(),
+            int("170141183460469231731687303715884105727")
+        );
+
+        let mut lexer = Lexer::from("= 170141183460469231731687303715884105728");
+        lexer.next().unwrap();
+        assert_eq!(lexer.next(), Err(LexerErr::InvalidNumber(2)));
+        assert_eq!(
+            Lexer::from("-170141183460469231731687303715884105729").next(),
+            Err(LexerErr::InvalidNumber(0))
+        );
+    }
+
+    #[test]
+    fn tokenize_approx() {
+        let float = |s: &str| Token::Literal(Literal::Float(String::from(s)));
+
+        let mut lexer = Lexer::from("~= 0.3 ± 0.1, ~=0.3 +/- 0.1 ~ 1");
+        lexer.set_mode(Mode::Condition);
+        for token in [
+            Token::Approx,
+            float("0.3"),
+            Token::PlusMinus,
+            float("0.1"),
+            Token::Delim,
+            Token::Approx,
+            float("0.3"),
+            Token::PlusMinus,
+            float("0.1"),
+            Token::Tilde,
+            Token::Literal(Literal::Integer(String::from("1"))),
+            Token::Eof,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+    }
+
+    #[test]
+    fn tokenize_regex() {
+        let mut lexer = Lexer::from(r"~ /^val\d+\/$/ / 2 ~ /open");
+        lexer.set_mode(Mode::Condition);
+        for token in [
+            Token::Tilde,
+            Token::Regex(String::from(r"^val\d+/$")),
+            Token::Arith(ArithOp::Div),
+            Token::Literal(Literal::Integer(String::from("2"))),
+            Token::Tilde,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+        assert_eq!(lexer.next(), Err(LexerErr::UnterminatedLiteral(21)));
+    }
+
+    #[test]
+    fn tokenize_dates() {
+        let date = |s: &str| Token::Literal(Literal::DateTime(String::from(s)));
+        let duration = |s: &str| Token::Literal(Literal::Duration(String::from(s)));
+
+        let mut lexer = Lexer::from("(2024-01-05, 3) 2024-01-05T10:30:00.5+02:00 PT1H30M PT");
+        for token in [
+            Token::LParen,
+            date("2024-01-05"),
+            Token::Delim,
+            Token::Literal(Literal::Integer(String::from("3"))),
+            Token::RParen,
+            date("2024-01-05T10:30:00.5+02:00"),
+            duration("PT1H30M"),
+            Token::Ident(String::from("PT")),
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+
+        let mut lexer = Lexer::from("> 2024-01-01T08:00Z - P1D 2024-02-30");
+        lexer.set_mode(Mode::Condition);
+        for token in [
+            Token::Op(OpKind::Gt),
+            date("2024-01-01T08:00Z"),
+            Token::Arith(ArithOp::Sub),
+            duration("P1D"),
+            // not a date, so numbers as before dates were lexed
+            Token::Literal(Literal::Integer(String::from("2024"))),
+            Token::Arith(ArithOp::Sub),
+            Token::Literal(Literal::Integer(String::from("02"))),
+            Token::Arith(ArithOp::Sub),
+            Token::Literal(Literal::Integer(String::from("30"))),
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+
+        let mut lexer = Lexer::from("1000-20-30, 2024-13-05T10:00");
+        for token in [
+            Token::Literal(Literal::Integer(String::from("1000"))),
+            Token::Delim,
+            Token::Literal(Literal::Integer(String::from("20"))),
+            Token::Delim,
+            Token::Literal(Literal::Integer(String::from("30"))),
+            Token::Delim,
+            Token::Literal(Literal::Integer(String::from("2024"))),
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+    }
+
+    #[test]
+    fn tokenize_path() {
+        let int = |s: &str| Token::Literal(Literal::Integer(String::from(s)));
+
+        let mut lexer = Lexer::from("$0.1.25 > .5, $1 .5");
+        lexer.set_mode(Mode::Condition);
+        for token in [
+            Token::Position(0),
+            Token::Dot,
+            int("1"),
+            Token::Dot,
+            int("25"),
+            Token::Op(OpKind::Gt),
+            Token::Literal(Literal::Float(String::from("0.5"))),
+            Token::Delim,
+            Token::Position(1),
+            Token::Literal(Literal::Float(String::from("0.5"))),
+            Token::Eof,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+    }
+
+    #[test]
+    fn tokenize_strings() {
+        let string = |s: &str| Token::Literal(Literal::String(String::from(s)));
+
+        let mut lexer = Lexer::from(r#"('eu', "it's", 'a\'b', "") 'open"#);
+        for token in [
+            Token::LParen,
+            string("eu"),
+            Token::Delim,
+            string("it's"),
+            Token::Delim,
+            string("a'b"),
+            Token::Delim,
+            string(""),
+            Token::RParen,
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+        assert_eq!(lexer.next(), Err(LexerErr::UnterminatedLiteral(27)));
+    }
+
+    #[test]
+    fn tokenize_key_format() {
+        let int = |s: &str| Token::Literal(Literal::Integer(String::from(s)));
+        let tokens = |text, format: KeyFormat| {
+            let mut lexer = Lexer::from(text);
+            lexer.set_format(format);
+
+            let mut tokens = Vec::new();
+
+            loop {
+                match lexer.next() {
+                    Ok(Token::Eof) => return tokens,
+                    Ok(token) => tokens.push(Ok(token)),
+                    Err(err) => {
+                        tokens.push(Err(err));
+                        return tokens;
+                    }
+                }
+            }
+        };
+
+        assert_eq!(
+            tokens("[1;5]", KeyFormat::strict(";").with_brackets(&[('[', ']')])),
+            vec![
+                Ok(Token::LParen),
+                Ok(int("1")),
+                Ok(Token::Delim),
+                Ok(int("5")),
+                Ok(Token::RParen),
+            ]
+        );
+        assert_eq!(
+            tokens("1-5", KeyFormat::lenient().with_delimiters(",")),
+            vec![Ok(Token::Literal(Literal::String(String::from("1-5"))))]
+        );
+        assert_eq!(
+            tokens("#1, 5 (x/y)", KeyFormat::lenient().with_delimiters(",")),
+            vec![
+                Ok(Token::Literal(Literal::String(String::from("#1")))),
+                Ok(Token::Delim),
+                Ok(int("5")),
+                Ok(Token::LParen),
+                Ok(Token::Literal(Literal::String(String::from("x/y")))),
+                Ok(Token::RParen),
+            ]
+        );
+        assert_eq!(
+            tokens("1/5", KeyFormat::strict(",")),
+            vec![Ok(int("1")), Err(LexerErr::UnexpectedChar('/', 1))]
+        );
+        assert_eq!(
+            tokens("(1, 5]", KeyFormat::strict(",")),
+            vec![
+                Ok(Token::LParen),
+                Ok(int("1")),
+                Ok(Token::Delim),
+                Ok(int("5")),
+                Err(LexerErr::UnexpectedChar(']', 5)),
+            ]
+        );
+        assert_eq!(
+            tokens("((1)", KeyFormat::strict(",")).last(),
+            Some(&Err(LexerErr::UnclosedBracket(0)))
+        );
+        assert_eq!(
+            tokens("(1))", KeyFormat::strict(",")).last(),
+            Some(&Err(LexerErr::UnexpectedChar(')', 3)))
+        );
+    }
+
+    #[test]
+    fn tokenize_strict() {
+        let mut lexer = Lexer::from("<5abc>=5 &|c>=3");
+        lexer.set_mode(Mode::Condition);
+        lexer.set_strict(true);
+        for token in [
+            Token::Op(OpKind::Lt),
+            Token::Literal(Literal::Integer(String::from("5"))),
+            Token::Ident(String::from("abc")),
+            Token::Op(OpKind::Ge),
+            Token::Literal(Literal::Integer(String::from("5"))),
+        ] {
+            assert_eq!(lexer.next().unwrap(), token);
+        }
+        assert_eq!(lexer.next(), Err(LexerErr::UnexpectedChar('&', 9)));
+
+        lexer.set("'é', $ 1");
+        assert_eq!(
+            lexer.next().unwrap(),
+            Token::Literal(Literal::String(String::from("é")))
+        );
+        assert_eq!(lexer.next().unwrap(), Token::Delim);
+        assert_eq!(lexer.next(), Err(LexerErr::UnexpectedChar('$', 6)));
+
+        lexer.set_strict(false);
+        lexer.set("1 & 2");
+        lexer.next().unwrap();
+        assert_eq!(lexer.next().unwrap(), Token::Delim);
+    }
+
+    #[test]
+    fn tokenize_spans() {
+        let mut lexer = Lexer::from("$1 >= 'ab', x");
+        lexer.set_mode(Mode::Condition);
+        let mut spans = vec![];
+        loop {
+            match lexer.next_spanned().unwrap() {
+                (Token::Eof, span) => break spans.push(span),
+                (_, span) => spans.push(span),
+            }
+        }
+
+        assert_eq!(
+            spans,
+            [(0, 2), (3, 5), (6, 10), (10, 11), (12, 13), (13, 13)]
+                .map(|(start, end)| Span::new(start, end))
+        );
+    }
+
     #[test]
     fn set() {
         let mut lexer = Lexer::from(">= 4");
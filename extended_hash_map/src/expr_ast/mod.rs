@@ -1,6 +1,15 @@
+pub mod builder;
+pub mod diagnostic;
+pub mod eval;
+pub mod format;
 mod lexer;
 pub mod node;
+pub mod optimize;
 pub mod parser;
+pub mod pattern;
+pub mod time;
 pub mod token;
+pub mod visit;
 
+pub use lexer::LexerErr;
 pub use parser::*;
@@ -1,27 +1,282 @@
-use super::token::{literal::Literal, op::OpKind};
+use std::fmt;
 
-#[derive(PartialEq, Eq, Debug)]
+use super::{
+    pattern::Pattern,
+    token::{
+        literal::Literal,
+        op::{ArithOp, OpKind},
+        Span,
+    },
+};
+
+/// A reference to a key component, either by its position in the key, by
+/// a path into nested tuples (`$0.1`) or by a name registered in the map's
+/// key schema.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Component {
+    Position(usize),
+    Path(Vec<usize>),
+    Name(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOp {
+    Neg, // -x
+    Abs, // abs(x)
+}
+
+/// An operand of a predicate: literals, key components and arithmetic on
+/// them.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expr {
+    Literal(Literal),
+    Component(Component),
+    Tuple(Vec<Expr>),
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        lhs: Box<Expr>,
+        op: ArithOp,
+        rhs: Box<Expr>,
+    },
+}
+
+impl Expr {
+    /// The highest key position this expression refers to.
+    pub fn max_position(&self) -> Option<usize> {
+        match self {
+            Expr::Literal(_) | Expr::Component(Component::Name(_)) => None,
+            Expr::Component(Component::Position(position)) => Some(*position),
+            Expr::Component(Component::Path(path)) => path.first().copied(),
+            Expr::Tuple(items) => items.iter().filter_map(Expr::max_position).max(),
+            Expr::Unary { expr, .. } => expr.max_position(),
+            Expr::Binary { lhs, rhs, .. } => lhs.max_position().max(rhs.max_position()),
+        }
+    }
+}
+
+/// A comparison, `lhs op rhs`.
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct BinOp {
-    pub lhs: Literal,
+    pub lhs: Expr,
     pub op: OpKind,
-    pub rhs: Literal,
+    pub rhs: Expr,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Approximate equality, `lhs ~= rhs ± tolerance`. Without an explicit
+/// tolerance the map's default one applies.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ApproxEq {
+    pub lhs: Expr,
+    pub rhs: Expr,
+    pub tolerance: Option<Expr>,
+}
+
+/// A string pattern predicate: `expr like '...'`, `expr glob '...'` or
+/// `expr ~ /.../`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Match {
+    pub expr: Expr,
+    pub pattern: Pattern,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IsKind {
+    Null,
+    NaN,
+    True,
+    False,
+}
+
+/// `expr is [not] null|nan|true|false`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Is {
+    pub expr: Expr,
+    pub negated: bool,
+    pub kind: IsKind,
+}
+
+/// A key component, which is a literal or a tuple of them, or a condition
+/// predicate.
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Node {
     Literal(Literal),
+    Tuple(Vec<Node>),
     BinOp(BinOp),
+    ApproxEq(ApproxEq),
+    Match(Match),
+    Is(Is),
+}
+
+impl Node {
+    /// The highest key position this predicate refers to.
+    pub fn max_position(&self) -> Option<usize> {
+        match self {
+            Node::Literal(_) | Node::Tuple(_) => None,
+            Node::BinOp(BinOp { lhs, rhs, .. }) => lhs.max_position().max(rhs.max_position()),
+            Node::ApproxEq(ApproxEq {
+                lhs,
+                rhs,
+                tolerance,
+            }) => lhs
+                .max_position()
+                .max(rhs.max_position())
+                .max(tolerance.as_ref().and_then(Expr::max_position)),
+            Node::Match(Match { expr, .. }) | Node::Is(Is { expr, .. }) => expr.max_position(),
+        }
+    }
+}
+
+/// The part of the source text a node was parsed from, with the spans of
+/// its parts in order: one per component of a tuple, the sides of a
+/// predicate (an implicit left-hand side is empty) followed by its
+/// tolerance or pattern, and the operands of an expression. Literals and
+/// components have no parts.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SpanTree {
+    pub span: Span,
+    pub parts: Vec<SpanTree>,
+}
+
+impl SpanTree {
+    pub fn leaf(span: Span) -> Self {
+        Self {
+            span,
+            parts: Vec::new(),
+        }
+    }
 }
 
-#[derive(Debug, Default)]
+/// A parsed key or condition. `spans[i]` locates `root[i]` and its parts in
+/// the source text.
+#[derive(Debug, Default, Clone)]
 pub struct Tree {
     pub root: Vec<Node>,
+    pub spans: Vec<SpanTree>,
 }
 
 impl Tree {
     pub fn len(&self) -> usize {
         self.root.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_empty()
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Component::Position(position) => write!(f, "${}", position),
+            Component::Path(path) => {
+                let path = path.iter().map(usize::to_string).collect::<Vec<_>>();
+                write!(f, "${}", path.join("."))
+            }
+            Component::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Writes `items` as a tuple, with a trailing delimiter if there is only
+/// one so that it reads back as a tuple.
+fn tuple<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    let items = items.iter().map(T::to_string).collect::<Vec<_>>();
+
+    match items[..] {
+        [ref item] => write!(f, "({},)", item),
+        _ => write!(f, "({})", items.join(", ")),
+    }
+}
+
+impl fmt::Display for Expr {
+    /// Writes the expression in canonical form, parenthesized only where
+    /// precedence requires it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(lit) => lit.fmt(f),
+            Expr::Component(component) => component.fmt(f),
+            Expr::Tuple(items) => tuple(f, items),
+            Expr::Unary {
+                op: UnaryOp::Abs,
+                expr,
+            } => write!(f, "abs({})", expr),
+            Expr::Unary {
+                op: UnaryOp::Neg,
+                expr,
+            } => match **expr {
+                Expr::Binary { .. } => write!(f, "-({})", expr),
+                // keep `- 1` from reading back as the literal `-1`
+                Expr::Literal(_)
+                | Expr::Unary {
+                    op: UnaryOp::Neg, ..
+                } => write!(f, "- {}", expr),
+                _ => write!(f, "-{}", expr),
+            },
+            Expr::Binary { lhs, op, rhs } => {
+                let precedence = |expr: &Expr| match expr {
+                    Expr::Binary { op, .. } => op.precedence(),
+                    _ => u8::MAX,
+                };
+
+                match precedence(lhs) < op.precedence() {
+                    true => write!(f, "({})", lhs)?,
+                    false => write!(f, "{}", lhs)?,
+                }
+                write!(f, " {} ", op)?;
+                match precedence(rhs) <= op.precedence() {
+                    true => write!(f, "({})", rhs),
+                    false => write!(f, "{}", rhs),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Literal(lit) => lit.fmt(f),
+            Node::Tuple(components) => tuple(f, components),
+            Node::BinOp(BinOp { lhs, op, rhs }) => write!(f, "{} {} {}", lhs, op, rhs),
+            Node::ApproxEq(ApproxEq {
+                lhs,
+                rhs,
+                tolerance,
+            }) => match tolerance {
+                Some(tolerance) => write!(f, "{} ~= {} ± {}", lhs, rhs, tolerance),
+                None => write!(f, "{} ~= {}", lhs, rhs),
+            },
+            Node::Match(Match { expr, pattern }) => write!(f, "{} {}", expr, pattern),
+            Node::Is(Is {
+                expr,
+                negated,
+                kind,
+            }) => {
+                let kind = match kind {
+                    IsKind::Null => "null",
+                    IsKind::NaN => "nan",
+                    IsKind::True => "true",
+                    IsKind::False => "false",
+                };
+                match negated {
+                    true => write!(f, "{} is not {}", expr, kind),
+                    false => write!(f, "{} is {}", expr, kind),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Tree {
+    /// Writes the components or predicates separated by `, `, which parses
+    /// back to the same nodes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nodes = self.root.iter().map(Node::to_string).collect::<Vec<_>>();
+        f.write_str(&nodes.join(", "))
+    }
 }
 
 #[cfg(test)]
@@ -30,7 +285,7 @@ mod tests {
 
     #[test]
     fn len() {
-        let mut tree = Tree { root: vec![] };
+        let mut tree = Tree::default();
 
         assert_eq!(tree.len(), 0);
 
@@ -0,0 +1,344 @@
+//! Simplification of conditions before they are evaluated: constant
+//! subexpressions are folded, comparisons of the same expression with
+//! constants merge into a single range and conditions that can never hold
+//! are detected up front.
+
+use std::cmp::Ordering;
+
+use super::{
+    eval::{Scope, Value},
+    node::{ApproxEq, BinOp, Component, Expr, Node, Tree},
+    token::{literal::Literal, op::OpKind},
+    visit::{rebuild_expr, Fold, Visitor},
+};
+
+/// A limit of a `Range`.
+#[derive(Debug, Clone)]
+pub struct Bound {
+    pub value: Value,
+    pub inclusive: bool,
+}
+
+/// Comparisons of `expr` with constants, merged: `expr` lies between the
+/// bounds present.
+#[derive(Debug, Clone)]
+pub struct Range {
+    pub expr: Expr,
+    pub lower: Option<Bound>,
+    pub upper: Option<Bound>,
+}
+
+impl Range {
+    /// The range a comparison `expr op value` bounds `expr` to, if any.
+    fn new(expr: Expr, op: OpKind, value: Value) -> Option<Self> {
+        let bound = |inclusive| {
+            Some(Bound {
+                value: value.clone(),
+                inclusive,
+            })
+        };
+        let (lower, upper) = match op {
+            OpKind::Eq => (bound(true), bound(true)),
+            OpKind::Ge => (bound(true), None),
+            OpKind::Gt => (bound(false), None),
+            OpKind::Le => (None, bound(true)),
+            OpKind::Lt => (None, bound(false)),
+            OpKind::Ne => return None,
+        };
+
+        Some(Self { expr, lower, upper })
+    }
+
+    /// Narrows the range to its intersection with `other`, or returns
+    /// false, unchanged, if their bounds do not compare.
+    fn intersect(&mut self, other: &Range) -> bool {
+        let lower = tighter(&self.lower, &other.lower, Ordering::Greater);
+        let upper = tighter(&self.upper, &other.upper, Ordering::Less);
+
+        match (lower, upper) {
+            (Some(lower), Some(upper)) => {
+                (self.lower, self.upper) = (lower, upper);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether no value can lie in the range.
+    fn is_empty(&self) -> bool {
+        let bounds = [&self.lower, &self.upper];
+
+        if bounds.iter().any(|bound| {
+            matches!(
+                bound,
+                Some(Bound {
+                    value: Value::Null,
+                    ..
+                })
+            )
+        }) {
+            // comparisons with null never hold
+            return true;
+        }
+
+        match (&self.lower, &self.upper) {
+            (Some(lower), Some(upper)) => match lower.value.partial_cmp(&upper.value) {
+                Some(Ordering::Greater) => true,
+                Some(Ordering::Equal) => !(lower.inclusive && upper.inclusive),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        let within = |bound: &Option<Bound>, outside: Ordering| match bound {
+            Some(bound) => match value.partial_cmp(&bound.value) {
+                Some(Ordering::Equal) => bound.inclusive,
+                Some(ordering) => ordering != outside,
+                None => false,
+            },
+            None => true,
+        };
+
+        within(&self.lower, Ordering::Less) && within(&self.upper, Ordering::Greater)
+    }
+}
+
+/// Of two bounds, the one further in the `inward` direction, or `None` if
+/// they do not compare.
+fn tighter(lhs: &Option<Bound>, rhs: &Option<Bound>, inward: Ordering) -> Option<Option<Bound>> {
+    match (lhs, rhs) {
+        (None, bound) | (bound, None) => Some(bound.clone()),
+        (Some(lhs), Some(rhs)) => Some(Some(match lhs.value.partial_cmp(&rhs.value)? {
+            Ordering::Equal => Bound {
+                value: lhs.value.clone(),
+                inclusive: lhs.inclusive && rhs.inclusive,
+            },
+            ordering if ordering == inward => lhs.clone(),
+            _ => rhs.clone(),
+        })),
+    }
+}
+
+/// A predicate of a plan.
+#[derive(Debug, Clone)]
+pub enum Step {
+    Node(Node),
+    Range(Range),
+}
+
+/// A condition ready to evaluate against keys.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+    /// False if the condition was proven to hold for no key.
+    pub satisfiable: bool,
+}
+
+impl Plan {
+    pub fn new(tree: &Tree) -> Self {
+        let empty = Tree::default();
+        let constants = Scope::new(&empty, &[], 0.0);
+        let mut plan = Plan {
+            steps: Vec::new(),
+            satisfiable: true,
+        };
+
+        for node in &tree.root {
+            let node = Constants(&constants).fold_node(node.clone());
+            // `~=` without a tolerance takes the map's, so it is left to the scan
+            let tolerant = matches!(
+                node,
+                Node::ApproxEq(ApproxEq {
+                    tolerance: None,
+                    ..
+                })
+            );
+
+            if !refers_to_key(&node) && !tolerant {
+                plan.satisfiable &= constants.predicate(&node);
+                continue;
+            }
+
+            match bound(&node) {
+                Some(range) => plan.merge(range),
+                None => plan.steps.push(Step::Node(node)),
+            }
+        }
+
+        plan.satisfiable &= plan.steps.iter().all(|step| match step {
+            Step::Range(range) => !range.is_empty(),
+            Step::Node(_) => true,
+        });
+
+        plan
+    }
+
+    /// Whether every step holds for the key of `scope`.
+    pub fn holds(&self, scope: &Scope) -> bool {
+        self.satisfiable
+            && self.steps.iter().all(|step| match step {
+                Step::Node(node) => scope.predicate(node),
+                Step::Range(range) => scope
+                    .eval(&range.expr)
+                    .is_some_and(|value| range.contains(&value)),
+            })
+    }
+
+    fn merge(&mut self, range: Range) {
+        let merged = self.steps.iter_mut().any(|step| match step {
+            Step::Range(existing) => existing.expr == range.expr && existing.intersect(&range),
+            Step::Node(_) => false,
+        });
+
+        if !merged {
+            self.steps.push(Step::Range(range));
+        }
+    }
+}
+
+/// Folds subexpressions without components into literals.
+struct Constants<'a, 'b>(&'a Scope<'b>);
+
+impl Fold for Constants<'_, '_> {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        let expr = rebuild_expr(self, expr);
+        let foldable = match &expr {
+            Expr::Unary { expr, .. } => matches!(**expr, Expr::Literal(_)),
+            Expr::Binary { lhs, rhs, .. } => {
+                matches!((&**lhs, &**rhs), (Expr::Literal(_), Expr::Literal(_)))
+            }
+            _ => false,
+        };
+
+        match self.0.eval(&expr).filter(|_| foldable).and_then(literal) {
+            Some(lit) => Expr::Literal(lit),
+            None => expr,
+        }
+    }
+}
+
+/// The literal of a value, where one is written the same way.
+fn literal(value: Value) -> Option<Literal> {
+    match value {
+        Value::Integer(int) => Some(Literal::Integer(int.to_string())),
+        Value::Float(float) => Some(Literal::float(float)),
+        Value::String(text) => Some(Literal::String(text)),
+        Value::Bool(b) => Some(Literal::Bool(b)),
+        Value::Null => Some(Literal::Null),
+        Value::Time(_) | Value::Duration(_) | Value::Tuple(_) => None,
+    }
+}
+
+fn refers_to_key(node: &Node) -> bool {
+    struct Components(bool);
+
+    impl Visitor for Components {
+        fn visit_component(&mut self, _component: &Component) {
+            self.0 = true;
+        }
+    }
+
+    let mut components = Components(false);
+    components.visit_node(node);
+
+    components.0
+}
+
+/// The range a comparison of an expression with a constant bounds it to.
+fn bound(node: &Node) -> Option<Range> {
+    let Node::BinOp(BinOp { lhs, op, rhs }) = node else {
+        return None;
+    };
+    let constant = |expr: &Expr| match expr {
+        Expr::Literal(lit) => Value::from_literal(lit),
+        _ => None,
+    };
+
+    match (constant(lhs), constant(rhs)) {
+        (None, Some(value)) => Range::new(lhs.clone(), *op, value),
+        (Some(value), None) => {
+            let op = match op {
+                OpKind::Lt => OpKind::Gt,
+                OpKind::Le => OpKind::Ge,
+                OpKind::Gt => OpKind::Lt,
+                OpKind::Ge => OpKind::Le,
+                op => *op,
+            };
+            Range::new(rhs.clone(), op, value)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr_ast::parser::Parser;
+
+    fn plan(condition: &str) -> Plan {
+        Plan::new(&Parser::new().parse_condition(condition).unwrap())
+    }
+
+    #[test]
+    fn fold() {
+        let empty = Tree::default();
+        let constants = Scope::new(&empty, &[], 0.0);
+        let fold = |condition| {
+            let tree = Parser::new().parse_condition(condition).unwrap();
+            Constants(&constants).fold_tree(tree).to_string()
+        };
+
+        assert_eq!(fold("$0 > 2 * 3 + 1"), "$0 > 7");
+        assert_eq!(fold("abs(- 2) + $1 = 'a'"), "2 + $1 = 'a'");
+        assert_eq!(fold("$0 = 7 / 2 - -1"), "$0 = 4.5");
+        assert_eq!(fold("$0 = 1 / 0"), "$0 = 1 / 0");
+        assert_eq!(fold("$2 < 2024-01-01 + P1D"), "$2 < 2024-01-01 + P1D");
+    }
+
+    #[test]
+    fn merge() {
+        let merged = plan(">= 1, $0 < 5, $0 > 0, $1 = 'a', $0 <> 3");
+
+        assert!(merged.satisfiable);
+        assert_eq!(merged.steps.len(), 3);
+
+        let Step::Range(range) = &merged.steps[0] else {
+            panic!("{:?}", merged.steps);
+        };
+        assert!(range.contains(&Value::Integer(1)));
+        assert!(range.contains(&Value::Float(4.5)));
+        assert!(!range.contains(&Value::Integer(5)));
+        assert!(!range.contains(&Value::Integer(0)));
+        assert!(!range.contains(&Value::String("2".to_string())));
+
+        // lower bounds that do not compare stay apart
+        assert_eq!(plan("$0 > 1, $0 < 'a'").steps.len(), 1);
+        assert_eq!(plan("$0 > 1, $0 > 'a'").steps.len(), 2);
+        assert_eq!(plan("5 > $0, 1 <= $0").steps.len(), 1);
+    }
+
+    #[test]
+    fn contradictions() {
+        assert!(!plan("<5, $0 >= 5").satisfiable);
+        assert!(!plan("= 1, $0 = 2").satisfiable);
+        assert!(!plan("> 2, $0 < 1 + 1").satisfiable);
+        assert!(
+            !Plan::new(&Tree {
+                root: vec![Node::BinOp(BinOp {
+                    lhs: Expr::Component(Component::Position(0)),
+                    op: OpKind::Ge,
+                    rhs: Expr::Literal(Literal::Null),
+                })],
+                ..Default::default()
+            })
+            .satisfiable
+        );
+        assert!(!plan("$1 > 1, 1 > 2").satisfiable);
+        assert!(!plan("$0 >= 1.5, $0 < 3 / 2").satisfiable);
+        assert!(plan("<= 5, $0 >= 5").satisfiable);
+        assert!(plan("$0 > 1, 2 > 1").satisfiable);
+        assert!(plan("$0 > 1, $0 < 'a'").satisfiable);
+    }
+}
@@ -1,20 +1,88 @@
+use std::{collections::HashMap, error::Error, fmt};
+
 use super::{
-    lexer::Lexer,
-    node::{BinOp, Node, Tree},
-    token::{literal::Literal, op::OpKind, Token},
+    diagnostic::{self, Diagnostic},
+    format::KeyFormat,
+    lexer::{self, Lexer, LexerErr, Mode},
+    node::{ApproxEq, BinOp, Component, Expr, Is, IsKind, Match, Node, SpanTree, Tree, UnaryOp},
+    pattern::{Pattern, Wildcard},
+    token::{literal::Literal, op::ArithOp, Span, Token},
 };
 
 #[derive(Debug, PartialEq)]
 pub enum ParserErr {
     InvalidToken(String),
     InvalidExpr,
+    /// A malformed regex, or any regex without the `regex` feature.
+    InvalidPattern(String),
     TokenMismatch,
-    Undefined,
+    /// Groups, brackets or unary operators nested deeper than
+    /// `MAX_NESTING`.
+    TooDeep,
+    Lexer(LexerErr),
+}
+
+impl fmt::Display for ParserErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParserErr::InvalidToken(word) => write!(f, "unexpected word `{}`", word),
+            ParserErr::InvalidExpr => write!(f, "invalid expression"),
+            ParserErr::InvalidPattern(source) => write!(f, "invalid pattern `{}`", source),
+            ParserErr::TokenMismatch => write!(f, "unexpected token"),
+            ParserErr::TooDeep => write!(f, "nested deeper than {} levels", MAX_NESTING),
+            ParserErr::Lexer(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for ParserErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParserErr::Lexer(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+const COMPARISONS: &[&str] = &["is", "like", "glob"];
+const IS_KINDS: &[&str] = &["not", "null", "nan", "true", "false"];
+const FUNCTIONS: &[&str] = &["abs"];
+/// The deepest nesting the recursive rules accept, well within the stack of
+/// any thread.
+const MAX_NESTING: usize = 256;
+/// Words that read as something else where a component name could be.
+const RESERVED: &[&str] = &["and", "is", "like", "glob", "true", "false"];
+
+/// Whether `name` reads back as the component `name` in a condition.
+pub(crate) fn is_name(name: &str) -> bool {
+    lexer::is_ident(name)
+        && !RESERVED
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(name))
 }
 
 #[derive(Debug)]
 pub struct Parser<'a> {
     current_token: Token,
+    current_span: Span,
+    last_end: usize,
+    /// Predicates that failed in a recovering parse, which keep their
+    /// positions.
+    failed: usize,
+    /// What the last failed rule expected and the keywords it accepts.
+    expected: (&'static str, &'static [&'static str]),
+    /// How many nested rules the current one is in.
+    nesting: usize,
+    /// Parentheses opened before the current token and not yet closed.
+    depth: usize,
+    /// Diagnostics of a recovering parse, or `None` if it stops at the
+    /// first error.
+    diagnostics: Option<Vec<Diagnostic>>,
+    /// Whether the parenthesis at an offset opens a group rather than an
+    /// expression, once `predicate` has tried it.
+    groups: HashMap<usize, bool>,
+    /// Spans of the parts parsed so far of the predicate being parsed.
+    spans: Vec<SpanTree>,
     lexer: Lexer<'a>,
 }
 
@@ -23,76 +91,684 @@ impl<'a> Parser<'a> {
         Default::default()
     }
 
+    /// Parses a key into its components, where bracketed components become
+    /// nested tuples. Brackets around the whole key only group it.
     pub fn parse(&mut self, text: &'a str) -> Result<Tree, ParserErr> {
         self.lexer.set(text);
+        self.lexer.set_mode(Mode::Key);
         self.next_token()?;
 
-        let mut tree = Tree::default();
+        if self.current_token != Token::LParen {
+            let (root, spans) = self.components(false, true)?;
+            return Ok(Tree { root, spans });
+        }
 
-        while !self.current_token.is_eof() {
-            if self.current_token.is_delim() {
-                self.next_token()?;
+        let (components, spans, span) = self.key_tuple()?;
+        self.next_token()?;
+
+        let (mut rest, mut rest_spans) = self.components(false, false)?;
+
+        if rest.is_empty() {
+            return Ok(Tree {
+                root: components,
+                spans,
+            });
+        }
+
+        rest.insert(0, Node::Tuple(components));
+        rest_spans.insert(0, SpanTree { span, parts: spans });
+
+        Ok(Tree {
+            root: rest,
+            spans: rest_spans,
+        })
+    }
+
+    /// Parses components up to the end of the key or, if `nested`, up to
+    /// the bracket closing the tuple. `empty` tells whether a delimiter
+    /// right away leaves an empty component.
+    fn components(
+        &mut self,
+        nested: bool,
+        mut empty: bool,
+    ) -> Result<(Vec<Node>, Vec<SpanTree>), ParserErr> {
+        let mut components = Vec::new();
+        let mut spans = Vec::new();
 
-                if self.current_token.is_eof() {
-                    break;
+        loop {
+            match self.current_token {
+                Token::Eof => return Ok((components, spans)),
+                Token::RParen if nested => return Ok((components, spans)),
+                // a stray closing bracket of a lenient format
+                Token::RParen => {}
+                Token::Delim => {
+                    // nothing since the last delimiter, so keep the position
+                    if empty {
+                        components.push(Node::Literal(Literal::Null));
+                        let start = self.current_span.start;
+                        spans.push(SpanTree::leaf(Span::new(start, start)));
+                    }
+                    empty = true;
+                }
+                _ if !empty && self.lexer.format().requires_delimiter() => {
+                    return Err(self.fail(ParserErr::TokenMismatch, "a delimiter", &[]));
+                }
+                Token::LParen => {
+                    let (tuple, parts, span) = self.key_tuple()?;
+                    components.push(Node::Tuple(tuple));
+                    spans.push(SpanTree { span, parts });
+                    empty = false;
+                }
+                _ => {
+                    components.push(Node::Literal(self.key_component()?));
+                    spans.push(SpanTree::leaf(self.current_span));
+                    empty = false;
                 }
             }
 
-            tree.root.push(Node::Literal(self.literal()?));
-
             self.next_token()?;
         }
+    }
 
-        Ok(tree)
+    /// Parses a bracketed tuple up to its closing bracket, which is left as
+    /// the current token. Returns the components with their spans and the
+    /// span of the whole tuple.
+    fn key_tuple(&mut self) -> Result<(Vec<Node>, Vec<SpanTree>, Span), ParserErr> {
+        let start = self.current_span.start;
+        self.next_token()?;
+
+        let (components, spans) = self.nested(|parser| parser.components(true, true))?;
+
+        Ok((components, spans, Span::new(start, self.current_span.end)))
+    }
+
+    /// Makes `parse_condition` reject chars that start no token instead of
+    /// skipping them as delimiters.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.lexer.set_strict(strict);
     }
 
+    /// Sets the grammar `parse` reads keys with.
+    pub fn set_key_format(&mut self, format: KeyFormat) {
+        self.lexer.set_format(format);
+    }
+
+    /// Locates `err`, returned by the last parse, in its source and
+    /// describes what was expected there.
+    pub fn diagnostic(&self, err: &ParserErr) -> Diagnostic {
+        let text = self.lexer.text();
+
+        let err = match err {
+            ParserErr::Lexer(err) => err,
+            _ => {
+                let (expected, keywords) = self.expected;
+                let diagnostic = Diagnostic::new(text, self.current_span, expected);
+                let word = match (err, &self.current_token) {
+                    (ParserErr::InvalidPattern(_), _) if cfg!(not(feature = "regex")) => {
+                        return diagnostic.help("regex patterns need the `regex` feature");
+                    }
+                    (ParserErr::InvalidToken(word), _) | (_, Token::Ident(word)) => word,
+                    _ => return diagnostic,
+                };
+
+                return match diagnostic::closest(word, keywords) {
+                    Some(keyword) => diagnostic.help(format!("did you mean `{}`?", keyword)),
+                    None => diagnostic,
+                };
+            }
+        };
+
+        let start = err.offset();
+        let next = text[start.min(text.len())..]
+            .chars()
+            .next()
+            .map_or(start, |ch| start + ch.len_utf8());
+        let span = Span::new(start, self.lexer.offset().max(next));
+
+        match *err {
+            LexerErr::InvalidNumber(_) => Diagnostic::new(text, span, "a number"),
+            LexerErr::InvalidTime(_) => Diagnostic::new(text, span, "a date or duration")
+                .help("dates are written `YYYY-MM-DD`, durations `P1DT2H`"),
+            LexerErr::UnterminatedLiteral(_) => {
+                Diagnostic::new(text, Span::new(start, text.len()), "a closing quote")
+                    .found("end of input")
+            }
+            LexerErr::UnexpectedChar(..) => {
+                Diagnostic::new(text, Span::new(start, next), "a delimiter")
+                    .help("remove it or quote it as part of a string")
+            }
+            LexerErr::UnclosedBracket(_) => {
+                Diagnostic::new(text, Span::new(start, next), "a closing bracket")
+                    .found("end of input")
+            }
+        }
+    }
+
+    /// Parses a condition into one `BinOp` per predicate. Predicates are
+    /// separated by delimiters or `and` and may be grouped in parentheses;
+    /// a predicate without a left-hand side refers to the key component at
+    /// its own position.
     pub fn parse_condition(&mut self, text: &'a str) -> Result<Tree, ParserErr> {
+        self.diagnostics = None;
+        self.condition(text)
+    }
+
+    /// Like `parse_condition`, but on an error skips the rest of the
+    /// predicate and goes on, returning the predicates that parsed along
+    /// with a diagnostic per error. Predicates keep their positions, failed
+    /// ones included, and an error in a group only skips its predicate.
+    pub fn parse_condition_recovering(&mut self, text: &'a str) -> (Tree, Vec<Diagnostic>) {
+        self.diagnostics = Some(Vec::new());
+        let tree = self.condition(text).unwrap_or_default();
+
+        (tree, self.diagnostics.take().unwrap_or_default())
+    }
+
+    /// Parses a condition, stopping at the first error unless `diagnostics`
+    /// collects them.
+    fn condition(&mut self, text: &'a str) -> Result<Tree, ParserErr> {
         self.lexer.set(text);
-        self.next_token()?;
+        self.lexer.set_mode(Mode::Condition);
+        self.failed = 0;
+        self.depth = 0;
+        self.groups.clear();
 
         let mut tree = Tree::default();
+        if let Err(err) = self.next_token() {
+            self.recover(err, 0, false)?;
+        }
+        self.predicates(&mut tree, false)?;
 
-        while !self.current_token.is_eof() {
-            if self.current_token.is_delim() {
-                self.next_token()?;
+        Ok(tree)
+    }
+
+    /// Parses predicates up to the end or, if `nested`, up to the
+    /// parenthesis closing their group, which is left as the current token.
+    fn predicates(&mut self, tree: &mut Tree, nested: bool) -> Result<(), ParserErr> {
+        loop {
+            let depth = self.depth;
+            let step = match self.current_token {
+                Token::RParen if nested => return Ok(()),
+                _ if self.current_token.is_eof() => {
+                    return match nested {
+                        true => Err(self.fail(ParserErr::InvalidExpr, "`)`", &[])),
+                        false => Ok(()),
+                    };
+                }
+                Token::Delim => self.next_token(),
+                _ if self.current_token.is_keyword("and") => self.next_token(),
+                _ => {
+                    let len = tree.len();
 
-                if self.current_token.is_eof() {
-                    break;
+                    self.predicate(tree).inspect_err(|_| {
+                        tree.root.truncate(len);
+                        tree.spans.truncate(len);
+                        self.failed += 1;
+                    })
                 }
+            };
+
+            if let Err(err) = step {
+                self.recover(err, depth, nested)?;
             }
+        }
+    }
 
-            tree.root.push(Node::BinOp(self.bin_op()?));
+    /// Reports `err` if the parse is recovering and skips the rest of the
+    /// predicate that failed with it, which started at paren `depth`: up to
+    /// the next delimiter outside the parentheses it opened, the end, or if
+    /// `nested` the parenthesis closing its group. Otherwise returns `err`.
+    fn recover(&mut self, err: ParserErr, depth: usize, nested: bool) -> Result<(), ParserErr> {
+        if self.diagnostics.is_none() {
+            return Err(err);
+        }
+        let diagnostic = self.diagnostic(&err);
+        let diagnostics = self.diagnostics.get_or_insert_with(Vec::new);
+        // an unclosed group fails each group around it the same way
+        if diagnostics.last() != Some(&diagnostic) {
+            diagnostics.push(diagnostic);
         }
 
-        Ok(tree)
+        let mut open = self.depth.saturating_sub(depth);
+        self.depth = depth;
+        // after a lexer error the current token precedes the failure
+        let mut stale = matches!(err, ParserErr::Lexer(_));
+
+        loop {
+            if !stale {
+                match self.current_token {
+                    Token::Eof => return Ok(()),
+                    Token::Delim if open == 0 => return Ok(()),
+                    Token::RParen if open == 0 && nested => return Ok(()),
+                    Token::RParen => open = open.saturating_sub(1),
+                    Token::LParen => open += 1,
+                    _ => {}
+                }
+            }
+
+            stale = match self.lexer.next_spanned() {
+                Ok((token, span)) => {
+                    self.current_token = token;
+                    self.current_span = span;
+                    false
+                }
+                Err(_) if self.lexer.skip_char() => true,
+                Err(_) => {
+                    self.current_token = Token::Eof;
+                    return Ok(());
+                }
+            };
+        }
+    }
+
+    /// Parses a single predicate, or a parenthesized group of them, into
+    /// `tree`. A leading parenthesis is first tried as a group and, failing
+    /// that, as the start of an arithmetic expression. If both fail, the
+    /// one that got further is taken. Either way the choice is kept, so a
+    /// parenthesis is only ever tried both ways once.
+    fn predicate(&mut self, tree: &mut Tree) -> Result<(), ParserErr> {
+        if self.current_token != Token::LParen {
+            return self.comparison_into(tree);
+        }
+
+        let start = self.current_span.start;
+        match self.groups.get(&start) {
+            Some(true) => return self.group(tree),
+            Some(false) => return self.comparison_into(tree),
+            None => {}
+        }
+
+        let checkpoint = self.checkpoint();
+        let (len, failed) = (tree.len(), self.failed);
+
+        // tried without recovering, as it may be an expression instead
+        let diagnostics = self.diagnostics.take();
+        let group = self.group(tree);
+        self.diagnostics = diagnostics;
+
+        if group.is_ok() {
+            self.groups.insert(start, true);
+            return Ok(());
+        }
+        let group_end = self.lexer.offset();
+
+        self.restore(checkpoint.clone());
+        self.failed = failed;
+        tree.root.truncate(len);
+        tree.spans.truncate(len);
+
+        let expr = self.comparison_into(tree);
+        let is_group = expr.is_err() && self.lexer.offset() < group_end;
+        self.groups.insert(start, is_group);
+
+        if !is_group {
+            return expr;
+        }
+
+        // a group after all, so parse it again for its error, or going on
+        // past its errors if recovering
+        self.restore(checkpoint);
+        tree.root.truncate(len);
+        tree.spans.truncate(len);
+        self.group(tree)
+    }
+
+    /// Parses a single predicate into `tree`.
+    fn comparison_into(&mut self, tree: &mut Tree) -> Result<(), ParserErr> {
+        let position = tree.len() + self.failed;
+        // parts left over from a predicate that failed
+        self.spans.clear();
+        let open = self.open();
+
+        tree.root.push(self.comparison(position)?);
+        tree.spans.push(self.spanned(open));
+
+        Ok(())
+    }
+
+    /// The parser's place in the text, to go back to with `restore`.
+    fn checkpoint(&self) -> (Lexer<'a>, Token, Span, usize, usize) {
+        (
+            self.lexer.clone(),
+            self.current_token.clone(),
+            self.current_span,
+            self.last_end,
+            self.depth,
+        )
+    }
+
+    fn restore(&mut self, checkpoint: (Lexer<'a>, Token, Span, usize, usize)) {
+        (
+            self.lexer,
+            self.current_token,
+            self.current_span,
+            self.last_end,
+            self.depth,
+        ) = checkpoint;
+    }
+
+    fn group(&mut self, tree: &mut Tree) -> Result<(), ParserErr> {
+        self.next_token()?;
+        self.nested(|parser| parser.predicates(tree, true))?;
+
+        self.next_token()
     }
 
     fn next_token(&mut self) -> Result<(), ParserErr> {
-        match self.lexer.next() {
-            Ok(token) => Ok(self.current_token = token),
-            Err(_) => Err(ParserErr::Undefined),
+        match self.lexer.next_spanned() {
+            Ok((token, span)) => {
+                match self.current_token {
+                    Token::LParen => self.depth += 1,
+                    Token::RParen => self.depth = self.depth.saturating_sub(1),
+                    _ => {}
+                }
+                self.last_end = self.current_span.end;
+                self.current_token = token;
+                self.current_span = span;
+                Ok(())
+            }
+            Err(err) => Err(ParserErr::Lexer(err)),
         }
     }
 
-    fn bin_op(&mut self) -> Result<BinOp, ParserErr> {
-        let mut bin_op = BinOp {
-            lhs: Literal::Integer(String::new()),
-            op: OpKind::Eq,
-            rhs: Literal::Integer(String::new()),
+    fn comparison(&mut self, position: usize) -> Result<Node, ParserErr> {
+        let lhs = if self.current_token.is_comparison() {
+            let start = self.current_span.start;
+            self.spans.push(SpanTree::leaf(Span::new(start, start)));
+
+            Expr::Component(Component::Position(position))
+        } else {
+            let lhs = self.expr()?;
+
+            if !self.current_token.is_comparison() {
+                let err = match self.current_token {
+                    Token::Ident(ref ident) => ParserErr::InvalidToken(ident.clone()),
+                    _ => ParserErr::InvalidExpr,
+                };
+                return Err(self.fail(err, "a comparison", COMPARISONS));
+            }
+            lhs
         };
 
         match self.current_token {
-            Token::Op(op) => bin_op.op = op,
-            _ => return Err(ParserErr::TokenMismatch),
+            Token::Op(op) => {
+                self.next_token()?;
+
+                Ok(Node::BinOp(BinOp {
+                    lhs,
+                    op,
+                    rhs: self.expr()?,
+                }))
+            }
+            Token::Approx => {
+                self.next_token()?;
+
+                let rhs = self.expr()?;
+                let tolerance = match self.current_token {
+                    Token::PlusMinus => {
+                        self.next_token()?;
+                        Some(self.expr()?)
+                    }
+                    _ => None,
+                };
+
+                Ok(Node::ApproxEq(ApproxEq {
+                    lhs,
+                    rhs,
+                    tolerance,
+                }))
+            }
+            _ if self.current_token.is_keyword("is") => self.is(lhs),
+            _ => Ok(Node::Match(Match {
+                expr: lhs,
+                pattern: self.pattern()?,
+            })),
         }
+    }
+
+    fn is(&mut self, expr: Expr) -> Result<Node, ParserErr> {
+        self.next_token()?;
+
+        let negated = self.current_token.is_keyword("not");
+        if negated {
+            self.next_token()?;
+        }
+
+        let kind = match self.current_token {
+            _ if self.current_token.is_keyword("null") => Ok(IsKind::Null),
+            _ if self.current_token.is_keyword("nan") => Ok(IsKind::NaN),
+            _ if self.current_token.is_keyword("true") => Ok(IsKind::True),
+            _ if self.current_token.is_keyword("false") => Ok(IsKind::False),
+            Token::Ident(ref ident) => Err(ParserErr::InvalidToken(ident.clone())),
+            _ => Err(ParserErr::TokenMismatch),
+        };
+        let kind = match kind {
+            Ok(kind) => kind,
+            Err(err) => return Err(self.fail(err, "`null`, `nan`, `true` or `false`", IS_KINDS)),
+        };
+        self.next_token()?;
+
+        Ok(Node::Is(Is {
+            expr,
+            negated,
+            kind,
+        }))
+    }
 
+    fn pattern(&mut self) -> Result<Pattern, ParserErr> {
+        let kind = std::mem::replace(&mut self.current_token, Token::Eof);
         self.next_token()?;
 
-        bin_op.rhs = self.literal()?;
+        let span = self.current_span;
+        self.spans.push(SpanTree::leaf(span));
+        let source = match self.current_token {
+            Token::Literal(Literal::String(ref mut source)) | Token::Regex(ref mut source) => {
+                std::mem::take(source)
+            }
+            _ => return Err(self.fail(ParserErr::TokenMismatch, "a quoted pattern", &[])),
+        };
+        self.next_token()?;
+
+        match kind {
+            Token::Tilde => Self::regex(source).map_err(|err| {
+                // point at the pattern rather than past it
+                self.current_span = span;
+                self.fail(err, "a valid regex", &[])
+            }),
+            _ if kind.is_keyword("like") => Ok(Pattern::Like(Wildcard::like(&source))),
+            _ if kind.is_keyword("glob") => Ok(Pattern::Glob(Wildcard::glob(&source))),
+            _ => Err(ParserErr::TokenMismatch),
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    fn regex(source: String) -> Result<Pattern, ParserErr> {
+        use super::pattern::Regex;
+
+        Regex::new(&source)
+            .map(Pattern::Regex)
+            .map_err(|_| ParserErr::InvalidPattern(source))
+    }
+
+    #[cfg(not(feature = "regex"))]
+    fn regex(source: String) -> Result<Pattern, ParserErr> {
+        Err(ParserErr::InvalidPattern(source))
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParserErr> {
+        self.binary(1)
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, ParserErr> {
+        let open = self.open();
+        let mut lhs = self.unary()?;
+
+        while let Token::Arith(op) = self.current_token {
+            if op.precedence() < min_precedence {
+                break;
+            }
+
+            self.next_token()?;
+
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Expr::Binary {
+                lhs: Box::new(lhs),
+                op,
+                rhs: Box::new(rhs),
+            };
+            self.close(open);
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParserErr> {
+        if self.current_token == Token::Arith(ArithOp::Sub) {
+            let open = self.open();
+            self.next_token()?;
+
+            let expr = self.nested(Self::unary)?;
+            self.close(open);
+
+            return Ok(Expr::Unary {
+                op: UnaryOp::Neg,
+                expr: Box::new(expr),
+            });
+        }
+
+        self.operand()
+    }
+
+    fn operand(&mut self) -> Result<Expr, ParserErr> {
+        let open = self.open();
+        let expr = match self.current_token {
+            _ if self.current_token.is_keyword("true") => Expr::Literal(Literal::Bool(true)),
+            _ if self.current_token.is_keyword("false") => Expr::Literal(Literal::Bool(false)),
+            Token::Ident(ref mut name) => {
+                let name = std::mem::take(name);
+                let span = self.current_span;
+                self.next_token()?;
+
+                let expr = match self.current_token {
+                    Token::LParen => self.call(name, span)?,
+                    _ => Expr::Component(Component::Name(name)),
+                };
+                self.close(open);
+
+                return Ok(expr);
+            }
+            Token::Position(position) => {
+                self.next_token()?;
+
+                let expr = self.path(position)?;
+                self.close(open);
+
+                return Ok(expr);
+            }
+            Token::LParen => {
+                self.next_token()?;
+
+                let expr = self.nested(Self::expr)?;
+
+                // a parenthesized expression keeps the span of its content
+                if !self.current_token.is_delim() {
+                    self.expect(Token::RParen)?;
+                    return Ok(expr);
+                }
+
+                let tuple = self.tuple(expr)?;
+                self.close(open);
+
+                return Ok(tuple);
+            }
+            _ => Expr::Literal(self.literal()?),
+        };
 
         self.next_token()?;
+        self.close(open);
 
-        Ok(bin_op)
+        Ok(expr)
+    }
+
+    /// Parses the `.1.2` indices following `$position`, if any.
+    fn path(&mut self, position: usize) -> Result<Expr, ParserErr> {
+        let mut path = vec![position];
+
+        while self.current_token == Token::Dot {
+            self.next_token()?;
+
+            match self.current_token {
+                Token::Literal(Literal::Integer(ref index)) => {
+                    path.push(index.parse().unwrap_or(usize::MAX))
+                }
+                _ => return Err(self.fail(ParserErr::TokenMismatch, "a path index", &[])),
+            }
+            self.next_token()?;
+        }
+
+        Ok(Expr::Component(match path[..] {
+            [position] => Component::Position(position),
+            _ => Component::Path(path),
+        }))
+    }
+
+    /// Parses the rest of a `(first, ...)` tuple, allowing a trailing
+    /// delimiter.
+    fn tuple(&mut self, first: Expr) -> Result<Expr, ParserErr> {
+        let mut items = vec![first];
+
+        while self.current_token.is_delim() {
+            self.next_token()?;
+
+            if self.current_token == Token::RParen {
+                break;
+            }
+            items.push(self.expr()?);
+        }
+        self.expect(Token::RParen)?;
+
+        Ok(Expr::Tuple(items))
+    }
+
+    fn call(&mut self, name: String, span: Span) -> Result<Expr, ParserErr> {
+        let op = match name.to_ascii_lowercase().as_str() {
+            "abs" => UnaryOp::Abs,
+            _ => {
+                self.current_span = span;
+                return Err(self.fail(ParserErr::InvalidToken(name), "a function", FUNCTIONS));
+            }
+        };
+
+        self.next_token()?;
+
+        let expr = self.nested(Self::expr)?;
+        self.expect(Token::RParen)?;
+
+        Ok(Expr::Unary {
+            op,
+            expr: Box::new(expr),
+        })
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParserErr> {
+        if self.current_token != token {
+            return Err(self.fail(ParserErr::TokenMismatch, "`)`", &[]));
+        }
+
+        self.next_token()
+    }
+
+    /// Bare words in keys are string components, except `null`, `nan`,
+    /// `true` and `false`.
+    fn key_component(&mut self) -> Result<Literal, ParserErr> {
+        match self.current_token {
+            _ if self.current_token.is_keyword("true") => Ok(Literal::Bool(true)),
+            _ if self.current_token.is_keyword("false") => Ok(Literal::Bool(false)),
+            _ if self.current_token.is_keyword("null") => Ok(Literal::Null),
+            _ if self.current_token.is_keyword("nan") => Ok(Literal::NaN),
+            Token::Ident(ref mut word) => Ok(Literal::String(std::mem::take(word))),
+            _ => self.literal(),
+        }
     }
 
     fn literal(&mut self) -> Result<Literal, ParserErr> {
@@ -103,15 +779,74 @@ impl<'a> Parser<'a> {
 
                 Ok(temp_lit)
             }
-            _ => return Err(ParserErr::TokenMismatch),
+            _ => Err(self.fail(ParserErr::TokenMismatch, "a value", &[])),
+        }
+    }
+
+    /// Starts a node at the current token: returns where it starts and
+    /// where the spans of its parts will begin.
+    fn open(&self) -> (usize, usize) {
+        (self.current_span.start, self.spans.len())
+    }
+
+    /// Ends the node started by `open` at the last token, taking the spans
+    /// pushed since as its parts.
+    fn spanned(&mut self, (start, parts): (usize, usize)) -> SpanTree {
+        SpanTree {
+            span: Span::new(start, self.last_end),
+            parts: self.spans.split_off(parts),
         }
     }
+
+    /// Like `spanned`, but keeps the node's span as a part of the node
+    /// containing it.
+    fn close(&mut self, open: (usize, usize)) {
+        let span = self.spanned(open);
+        self.spans.push(span);
+    }
+
+    /// Applies a rule one level deeper, failing beyond `MAX_NESTING` levels
+    /// rather than overflowing the stack.
+    fn nested<T>(
+        &mut self,
+        rule: impl FnOnce(&mut Self) -> Result<T, ParserErr>,
+    ) -> Result<T, ParserErr> {
+        if self.nesting == MAX_NESTING {
+            return Err(self.fail(ParserErr::TooDeep, "less nesting", &[]));
+        }
+
+        self.nesting += 1;
+        let result = rule(self);
+        self.nesting -= 1;
+
+        result
+    }
+
+    /// Records what the failing rule expected, for `diagnostic`.
+    fn fail(
+        &mut self,
+        err: ParserErr,
+        expected: &'static str,
+        keywords: &'static [&'static str],
+    ) -> ParserErr {
+        self.expected = (expected, keywords);
+        err
+    }
 }
 
 impl Default for Parser<'_> {
     fn default() -> Self {
         Self {
             current_token: Token::Eof,
+            current_span: Span::default(),
+            last_end: 0,
+            failed: 0,
+            expected: ("", &[]),
+            nesting: 0,
+            depth: 0,
+            diagnostics: None,
+            groups: HashMap::new(),
+            spans: Vec::new(),
             lexer: Lexer::from("\0"),
         }
     }
@@ -120,6 +855,7 @@ impl Default for Parser<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::expr_ast::token::op::OpKind;
 
     fn condition_exprs_and_trees() -> (Vec<&'static str>, Vec<Tree>) {
         (
@@ -131,99 +867,138 @@ mod tests {
                 "<5abc>=5 &|c>=3",
                 "a>=2",
                 " < 5 >=5 >= 3",
+                "y >= 5 and x < 10",
+                "$0 == $2, x != y",
             ],
             vec![
                 Tree {
                     root: vec![Node::BinOp(BinOp {
-                        lhs: Literal::Integer(String::new()),
+                        lhs: Expr::Component(Component::Position(0)),
                         op: OpKind::Ge,
-                        rhs: Literal::Integer("4".to_string()),
+                        rhs: Expr::Literal(Literal::Integer("4".to_string())),
                     })],
+                    ..Default::default()
                 },
                 Tree {
                     root: vec![Node::BinOp(BinOp {
-                        lhs: Literal::Integer(String::new()),
+                        lhs: Expr::Component(Component::Position(0)),
                         op: OpKind::Lt,
-                        rhs: Literal::Float("3.5".to_string()),
+                        rhs: Expr::Literal(Literal::Float("3.5".to_string())),
                     })],
+                    ..Default::default()
                 },
                 Tree {
                     root: vec![
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Position(0)),
                             op: OpKind::Gt,
-                            rhs: Literal::Integer("0".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("0".to_string())),
                         }),
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Position(1)),
                             op: OpKind::Lt,
-                            rhs: Literal::Integer("10".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("10".to_string())),
                         }),
                     ],
+                    ..Default::default()
                 },
                 Tree {
                     root: vec![
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Position(0)),
                             op: OpKind::Lt,
-                            rhs: Literal::Integer("5".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("5".to_string())),
                         }),
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Position(1)),
                             op: OpKind::Ge,
-                            rhs: Literal::Integer("5".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("5".to_string())),
                         }),
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Position(2)),
                             op: OpKind::Ge,
-                            rhs: Literal::Integer("3".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("3".to_string())),
                         }),
                     ],
+                    ..Default::default()
                 },
                 Tree {
                     root: vec![
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Position(0)),
                             op: OpKind::Lt,
-                            rhs: Literal::Integer("5".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("5".to_string())),
                         }),
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Name("abc".to_string())),
                             op: OpKind::Ge,
-                            rhs: Literal::Integer("5".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("5".to_string())),
                         }),
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Name("c".to_string())),
                             op: OpKind::Ge,
-                            rhs: Literal::Integer("3".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("3".to_string())),
                         }),
                     ],
+                    ..Default::default()
                 },
                 Tree {
                     root: vec![Node::BinOp(BinOp {
-                        lhs: Literal::Integer(String::new()),
+                        lhs: Expr::Component(Component::Name("a".to_string())),
                         op: OpKind::Ge,
-                        rhs: Literal::Integer("2".to_string()),
+                        rhs: Expr::Literal(Literal::Integer("2".to_string())),
                     })],
+                    ..Default::default()
                 },
                 Tree {
                     root: vec![
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Position(0)),
                             op: OpKind::Lt,
-                            rhs: Literal::Integer("5".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("5".to_string())),
+                        }),
+                        Node::BinOp(BinOp {
+                            lhs: Expr::Component(Component::Position(1)),
+                            op: OpKind::Ge,
+                            rhs: Expr::Literal(Literal::Integer("5".to_string())),
                         }),
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Position(2)),
                             op: OpKind::Ge,
-                            rhs: Literal::Integer("5".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("3".to_string())),
                         }),
+                    ],
+                    ..Default::default()
+                },
+                Tree {
+                    root: vec![
                         Node::BinOp(BinOp {
-                            lhs: Literal::Integer(String::new()),
+                            lhs: Expr::Component(Component::Name("y".to_string())),
                             op: OpKind::Ge,
-                            rhs: Literal::Integer("3".to_string()),
+                            rhs: Expr::Literal(Literal::Integer("5".to_string())),
+                        }),
+                        Node::BinOp(BinOp {
+                            lhs: Expr::Component(Component::Name("x".to_string())),
+                            op: OpKind::Lt,
+                            rhs: Expr::Literal(Literal::Integer("10".to_string())),
+                        }),
+                    ],
+                    ..Default::default()
+                },
+                Tree {
+                    root: vec![
+                        Node::BinOp(BinOp {
+                            lhs: Expr::Component(Component::Position(0)),
+                            op: OpKind::Eq,
+                            rhs: Expr::Component(Component::Position(2)),
+                        }),
+                        Node::BinOp(BinOp {
+                            lhs: Expr::Component(Component::Name("x".to_string())),
+                            op: OpKind::Ne,
+                            rhs: Expr::Component(Component::Name("y".to_string())),
                         }),
                     ],
+                    ..Default::default()
                 },
             ],
         )
@@ -231,16 +1006,33 @@ mod tests {
 
     fn exprs_and_trees() -> (Vec<&'static str>, Vec<Tree>) {
         (
-            vec!["(5, 5)", "value1"],
+            vec!["(5, 5)", "-3, 5.5", "value1", "(eu, 'new york', 5)"],
             vec![
                 Tree {
                     root: vec![
                         Node::Literal(Literal::Integer(String::from("5"))),
                         Node::Literal(Literal::Integer(String::from("5"))),
                     ],
+                    ..Default::default()
+                },
+                Tree {
+                    root: vec![
+                        Node::Literal(Literal::Integer(String::from("-3"))),
+                        Node::Literal(Literal::Float(String::from("5.5"))),
+                    ],
+                    ..Default::default()
+                },
+                Tree {
+                    root: vec![Node::Literal(Literal::String(String::from("value1")))],
+                    ..Default::default()
                 },
                 Tree {
-                    root: vec![Node::Literal(Literal::Integer(String::from("1")))],
+                    root: vec![
+                        Node::Literal(Literal::String(String::from("eu"))),
+                        Node::Literal(Literal::String(String::from("new york"))),
+                        Node::Literal(Literal::Integer(String::from("5"))),
+                    ],
+                    ..Default::default()
                 },
             ],
         )
@@ -275,6 +1067,570 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_arithmetic() {
+        let mut parser = Parser::new();
+        let int = |s: &str| Box::new(Expr::Literal(Literal::Integer(s.to_string())));
+        let x = || Box::new(Expr::Component(Component::Name("x".to_string())));
+
+        assert_eq!(
+            parser.parse_condition("x * 2 + 1 > 10").unwrap().root,
+            vec![Node::BinOp(BinOp {
+                lhs: Expr::Binary {
+                    lhs: Box::new(Expr::Binary {
+                        lhs: x(),
+                        op: ArithOp::Mul,
+                        rhs: int("2"),
+                    }),
+                    op: ArithOp::Add,
+                    rhs: int("1"),
+                },
+                op: OpKind::Gt,
+                rhs: *int("10"),
+            })]
+        );
+        assert_eq!(
+            parser.parse_condition("((x + 1) > -abs(2))").unwrap().root,
+            vec![Node::BinOp(BinOp {
+                lhs: Expr::Binary {
+                    lhs: x(),
+                    op: ArithOp::Add,
+                    rhs: int("1"),
+                },
+                op: OpKind::Gt,
+                rhs: Expr::Unary {
+                    op: UnaryOp::Neg,
+                    expr: Box::new(Expr::Unary {
+                        op: UnaryOp::Abs,
+                        expr: int("2"),
+                    }),
+                },
+            })]
+        );
+        assert_eq!(
+            parser.parse_condition("sqrt(x) > 1").unwrap_err(),
+            ParserErr::InvalidToken("sqrt".to_string())
+        );
+        assert!(parser.parse_condition("(x + 1 > 2").is_err());
+    }
+
+    #[test]
+    fn parse_approx() {
+        let mut parser = Parser::new();
+        let float = |s: &str| Expr::Literal(Literal::Float(s.to_string()));
+
+        assert_eq!(
+            parser
+                .parse_condition("~= 0.3, y ~= 0.5 ± 0.1")
+                .unwrap()
+                .root,
+            vec![
+                Node::ApproxEq(ApproxEq {
+                    lhs: Expr::Component(Component::Position(0)),
+                    rhs: float("0.3"),
+                    tolerance: None,
+                }),
+                Node::ApproxEq(ApproxEq {
+                    lhs: Expr::Component(Component::Name("y".to_string())),
+                    rhs: float("0.5"),
+                    tolerance: Some(float("0.1")),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_pattern() {
+        let mut parser = Parser::new();
+
+        assert_eq!(
+            parser
+                .parse_condition("like 'val%', y glob \"v*\"")
+                .unwrap()
+                .root,
+            vec![
+                Node::Match(Match {
+                    expr: Expr::Component(Component::Position(0)),
+                    pattern: Pattern::Like(Wildcard::like("val%")),
+                }),
+                Node::Match(Match {
+                    expr: Expr::Component(Component::Name("y".to_string())),
+                    pattern: Pattern::Glob(Wildcard::glob("v*")),
+                }),
+            ]
+        );
+        assert_eq!(
+            parser.parse_condition("like 5").unwrap_err(),
+            ParserErr::TokenMismatch
+        );
+
+        #[cfg(feature = "regex")]
+        assert_eq!(
+            parser.parse_condition("~ /(/").unwrap_err(),
+            ParserErr::InvalidPattern("(".to_string())
+        );
+        #[cfg(not(feature = "regex"))]
+        assert_eq!(
+            parser.parse_condition("~ /^v/").unwrap_err(),
+            ParserErr::InvalidPattern("^v".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_null() {
+        let mut parser = Parser::new();
+        let literals = |text| {
+            Parser::new()
+                .parse(text)
+                .unwrap()
+                .root
+                .into_iter()
+                .map(|node| match node {
+                    Node::Literal(lit) => lit,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let int = |s: &str| Literal::Integer(s.to_string());
+
+        assert_eq!(literals("1, , 5"), vec![int("1"), Literal::Null, int("5")]);
+        assert_eq!(literals("1,,5"), vec![int("1"), Literal::Null, int("5")]);
+        assert_eq!(literals("(, 5)"), vec![Literal::Null, int("5")]);
+        assert_eq!(literals("(1, 5,)"), vec![int("1"), int("5")]);
+        assert_eq!(
+            literals("(1, NaN, null)"),
+            vec![int("1"), Literal::NaN, Literal::Null]
+        );
+
+        assert_eq!(
+            parser
+                .parse_condition("is null, $1 is not nan")
+                .unwrap()
+                .root,
+            vec![
+                Node::Is(Is {
+                    expr: Expr::Component(Component::Position(0)),
+                    negated: false,
+                    kind: IsKind::Null,
+                }),
+                Node::Is(Is {
+                    expr: Expr::Component(Component::Position(1)),
+                    negated: true,
+                    kind: IsKind::NaN,
+                }),
+            ]
+        );
+        assert_eq!(
+            parser.parse_condition("is empty").unwrap_err(),
+            ParserErr::InvalidToken("empty".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_bool() {
+        let mut parser = Parser::new();
+
+        assert_eq!(
+            parser.parse("(1, true, FALSE)").unwrap().root,
+            vec![
+                Node::Literal(Literal::Integer("1".to_string())),
+                Node::Literal(Literal::Bool(true)),
+                Node::Literal(Literal::Bool(false)),
+            ]
+        );
+        assert_eq!(
+            parser
+                .parse_condition("= true, flag is not false")
+                .unwrap()
+                .root,
+            vec![
+                Node::BinOp(BinOp {
+                    lhs: Expr::Component(Component::Position(0)),
+                    op: OpKind::Eq,
+                    rhs: Expr::Literal(Literal::Bool(true)),
+                }),
+                Node::Is(Is {
+                    expr: Expr::Component(Component::Name("flag".to_string())),
+                    negated: true,
+                    kind: IsKind::False,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_key_format() {
+        let mut parser = Parser::new();
+        parser.set_key_format(KeyFormat::strict(",;"));
+
+        assert_eq!(parser.parse("(1; 5, )").unwrap().len(), 2);
+        assert_eq!(parser.parse("(1 5)").unwrap_err(), ParserErr::TokenMismatch);
+        assert_eq!(
+            parser.parse("1/5").unwrap_err(),
+            ParserErr::Lexer(LexerErr::UnexpectedChar('/', 1))
+        );
+        assert_eq!(
+            parser.parse("(1, 5").unwrap_err(),
+            ParserErr::Lexer(LexerErr::UnclosedBracket(0))
+        );
+
+        parser.set_key_format(KeyFormat::lenient().with_delimiters(","));
+
+        assert_eq!(parser.parse("(1 5").unwrap().len(), 2);
+        assert_eq!(parser.parse("1/5,,").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn parse_nested() {
+        let mut parser = Parser::new();
+        let int = |s: &str| Node::Literal(Literal::Integer(s.to_string()));
+
+        assert_eq!(
+            parser.parse("((1, 2), 3)").unwrap().root,
+            vec![Node::Tuple(vec![int("1"), int("2")]), int("3")]
+        );
+        assert_eq!(
+            parser.parse("1, (2, (), (3,))").unwrap().root,
+            vec![
+                int("1"),
+                Node::Tuple(vec![
+                    int("2"),
+                    Node::Tuple(vec![]),
+                    Node::Tuple(vec![int("3")])
+                ])
+            ]
+        );
+        assert_eq!(parser.parse("(1, (2, 3)").unwrap().len(), 2);
+
+        assert_eq!(
+            parser
+                .parse_condition("$0.1 > 1, = (1, $1 + 1,)")
+                .unwrap()
+                .root,
+            vec![
+                Node::BinOp(BinOp {
+                    lhs: Expr::Component(Component::Path(vec![0, 1])),
+                    op: OpKind::Gt,
+                    rhs: Expr::Literal(Literal::Integer("1".to_string())),
+                }),
+                Node::BinOp(BinOp {
+                    lhs: Expr::Component(Component::Position(1)),
+                    op: OpKind::Eq,
+                    rhs: Expr::Tuple(vec![
+                        Expr::Literal(Literal::Integer("1".to_string())),
+                        Expr::Binary {
+                            lhs: Box::new(Expr::Component(Component::Position(1))),
+                            op: ArithOp::Add,
+                            rhs: Box::new(Expr::Literal(Literal::Integer("1".to_string()))),
+                        },
+                    ]),
+                }),
+            ]
+        );
+        assert_eq!(
+            parser.parse_condition("$0.x > 1").unwrap_err(),
+            ParserErr::InvalidExpr
+        );
+    }
+
+    #[test]
+    fn parse_spans() {
+        let mut parser = Parser::new();
+        let spans = |tree: Tree| {
+            tree.spans
+                .iter()
+                .map(|spans| (spans.span.start, spans.span.end))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            spans(parser.parse("1, 'ab' ,, x").unwrap()),
+            [(0, 1), (3, 7), (9, 9), (11, 12)]
+        );
+        assert_eq!(
+            spans(parser.parse("((1, 2), 3)").unwrap()),
+            [(1, 7), (9, 10)]
+        );
+        assert_eq!(spans(parser.parse("(1, 2) 3").unwrap()), [(0, 6), (7, 8)]);
+        assert_eq!(
+            spans(parser.parse_condition(">= 1, $0 + 1 < 5 ,x = 'a'").unwrap()),
+            [(0, 4), (6, 16), (18, 25)]
+        );
+    }
+
+    #[test]
+    fn parse_nested_spans() {
+        // writes each part's text after its node's, in brackets
+        fn parts(text: &str, spans: &SpanTree) -> String {
+            let source = &text[spans.span.start..spans.span.end];
+            match spans.parts[..] {
+                [] => format!("`{}`", source),
+                _ => {
+                    let parts = spans.parts.iter().map(|part| parts(text, part));
+                    format!("`{}`[{}]", source, parts.collect::<Vec<_>>().join(" "))
+                }
+            }
+        }
+        let mut parser = Parser::new();
+        let mut spans = |text: &'static str, key: bool| {
+            let tree = match key {
+                true => parser.parse(text),
+                false => parser.parse_condition(text),
+            };
+            let spans = tree.unwrap().spans;
+            spans
+                .iter()
+                .map(|spans| parts(text, spans))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            spans("(1, (2)) 3, (4, (5))", true),
+            [
+                "`(1, (2))`[`1` `(2)`[`2`]]",
+                "`3`",
+                "`(4, (5))`[`4` `(5)`[`5`]]"
+            ]
+        );
+        assert_eq!(
+            spans(">= 1, $0 + abs(-x) * 2 < (1, 'a')", false),
+            [
+                "`>= 1`[`` `1`]",
+                "`$0 + abs(-x) * 2 < (1, 'a')`[`$0 + abs(-x) * 2`[`$0` `abs(-x) * 2`\
+                 [`abs(-x)`[`-x`[`x`]] `2`]] `(1, 'a')`[`1` `'a'`]]",
+            ]
+        );
+        assert_eq!(
+            spans("(x ~= (1) ± 0.5) and y like 'a%', $1 is not null", false),
+            [
+                "`x ~= (1) ± 0.5`[`x` `1` `0.5`]",
+                "`y like 'a%'`[`y` `'a%'`]",
+                "`$1 is not null`[`$1`]",
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnostic() {
+        let mut parser = Parser::new();
+        let mut diagnose = |text| {
+            let err = parser.parse_condition(text).unwrap_err();
+            let diagnostic = parser.diagnostic(&err);
+
+            (
+                (diagnostic.span.start, diagnostic.span.end),
+                diagnostic.expected,
+                diagnostic.found,
+                diagnostic.help,
+            )
+        };
+
+        assert_eq!(
+            diagnose("$0 > 1, x lik 'a'"),
+            (
+                (10, 13),
+                "a comparison".to_string(),
+                "`lik`".to_string(),
+                Some("did you mean `like`?".to_string())
+            )
+        );
+        // the group got further than `(x` as an expression
+        assert_eq!(
+            diagnose("(x > 1, y lik 'a')"),
+            (
+                (10, 13),
+                "a comparison".to_string(),
+                "`lik`".to_string(),
+                Some("did you mean `like`?".to_string())
+            )
+        );
+        assert_eq!(
+            diagnose("(1 + 2) * 3 >"),
+            (
+                (13, 13),
+                "a value".to_string(),
+                "end of input".to_string(),
+                None
+            )
+        );
+        assert_eq!(
+            diagnose("x is nul"),
+            (
+                (5, 8),
+                "`null`, `nan`, `true` or `false`".to_string(),
+                "`nul`".to_string(),
+                Some("did you mean `null`?".to_string())
+            )
+        );
+        assert_eq!(
+            diagnose(">= sbs(1)"),
+            (
+                (3, 6),
+                "a function".to_string(),
+                "`sbs`".to_string(),
+                Some("did you mean `abs`?".to_string())
+            )
+        );
+        assert_eq!(
+            diagnose("x > (1 + 2"),
+            (
+                (10, 10),
+                "`)`".to_string(),
+                "end of input".to_string(),
+                None
+            )
+        );
+        assert_eq!(
+            diagnose("x = 'abc"),
+            (
+                (4, 8),
+                "a closing quote".to_string(),
+                "end of input".to_string(),
+                None
+            )
+        );
+        assert_eq!(
+            diagnose("> 0x1_0000_0000_0000_0000_0000_0000_0000_0000, 1"),
+            (
+                (2, 45),
+                "a number".to_string(),
+                "`0x1_0000_0000_0000_0000_0000_0000_0000_0000`".to_string(),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn parse_recovering() {
+        let mut parser = Parser::new();
+        let found = |diagnostics: Vec<Diagnostic>| {
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| diagnostic.found)
+                .collect::<Vec<_>>()
+        };
+
+        let (tree, diagnostics) =
+            parser.parse_condition_recovering("x lik 'a', >= 1, y is nul, = 'b'");
+        assert_eq!(
+            tree.root,
+            vec![
+                Node::BinOp(BinOp {
+                    lhs: Expr::Component(Component::Position(1)),
+                    op: OpKind::Ge,
+                    rhs: Expr::Literal(Literal::Integer("1".to_string())),
+                }),
+                Node::BinOp(BinOp {
+                    lhs: Expr::Component(Component::Position(3)),
+                    op: OpKind::Eq,
+                    rhs: Expr::Literal(Literal::String("b".to_string())),
+                }),
+            ]
+        );
+        assert_eq!(found(diagnostics), ["`lik`", "`nul`"]);
+
+        let (tree, diagnostics) = parser.parse_condition_recovering(">= 1, = 'a");
+        assert_eq!(tree.len(), 1);
+        assert_eq!(found(diagnostics), ["end of input"]);
+
+        // an error in a group skips one predicate of it, keeping positions
+        let (tree, diagnostics) = parser.parse_condition_recovering("(> 1, y lik 'a', > 2), > 0");
+        assert_eq!(tree.to_string(), "$0 > 1, $2 > 2, $3 > 0");
+        assert_eq!(found(diagnostics), ["`lik`"]);
+
+        let (tree, diagnostics) = parser.parse_condition_recovering("(x > (1 lik 2), > 3), > 4");
+        assert_eq!(tree.to_string(), "$1 > 3, $2 > 4");
+        assert_eq!(found(diagnostics), ["`lik`"]);
+
+        let (tree, diagnostics) = parser.parse_condition_recovering("> 1 ), > 2");
+        assert_eq!(tree.to_string(), "$0 > 1, $2 > 2");
+        assert_eq!(found(diagnostics), ["`)`"]);
+
+        // each unclosed group fails the same way, reported once
+        let text = format!("{}$0 > ", "(".repeat(100));
+        let (tree, diagnostics) = parser.parse_condition_recovering(&text);
+        assert!(tree.is_empty());
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.expected.as_str())
+                .collect::<Vec<_>>(),
+            ["a value", "`)`"]
+        );
+
+        parser.set_strict(true);
+
+        let (tree, diagnostics) = parser.parse_condition_recovering("> 1 & x, > 2; < (3");
+        // `> 1` fails too, on the char after it
+        assert_eq!(tree.spans[0].span, Span::new(9, 12));
+        assert_eq!(tree.len(), 1);
+        assert_eq!(found(diagnostics), ["`&`", "end of input"]);
+
+        let (tree, diagnostics) = parser.parse_condition_recovering("> 1, > 2");
+        assert_eq!((tree.len(), diagnostics.len()), (2, 0));
+    }
+
+    #[test]
+    fn parse_nesting() {
+        let nested =
+            |depth, inner: &str| format!("{}{}{}", "(".repeat(depth), inner, ")".repeat(depth));
+
+        for text in [
+            nested(MAX_NESTING, "> 1"),
+            format!("$0 > {}", nested(MAX_NESTING - 1, "1")),
+            format!("{}$0 > 1", "- ".repeat(MAX_NESTING)),
+        ] {
+            assert!(Parser::new().parse_condition(&text).is_ok(), "{}", text);
+        }
+        assert!(Parser::new().parse(&nested(MAX_NESTING, "1")).is_ok());
+
+        let deep = "(".repeat(20_000);
+        for text in [
+            deep.clone(),
+            nested(MAX_NESTING + 1, "> 1"),
+            format!("$0 > {}", nested(20_000, "1")),
+            format!("$0 > {}1", "abs(".repeat(20_000)),
+            format!("{}$0 > 1", "- ".repeat(20_000)),
+        ] {
+            let mut parser = Parser::new();
+            let err = parser.parse_condition(&text).unwrap_err();
+
+            assert_eq!(err, ParserErr::TooDeep);
+            assert_eq!(parser.diagnostic(&err).expected, "less nesting");
+        }
+        assert_eq!(Parser::new().parse(&deep).unwrap_err(), ParserErr::TooDeep);
+
+        let (_, diagnostics) = Parser::new().parse_condition_recovering(&deep);
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn parse_condition_missing_op() {
+        let mut parser = Parser::new();
+
+        assert_eq!(
+            parser.parse_condition("x y 5").unwrap_err(),
+            ParserErr::InvalidToken("y".to_string())
+        );
+        assert_eq!(
+            parser.parse_condition("x 5").unwrap_err(),
+            ParserErr::InvalidExpr
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let mut parser = Parser::new();
+
+        assert_eq!(parser.parse("1 <").unwrap_err(), ParserErr::TokenMismatch);
+        assert_eq!(
+            parser
+                .parse("1, 0x1_0000_0000_0000_0000_0000_0000_0000_0000")
+                .unwrap_err(),
+            ParserErr::Lexer(LexerErr::InvalidNumber(3))
+        );
+    }
+
     #[test]
     #[should_panic]
     fn parse_invalid_condition() {
@@ -0,0 +1,241 @@
+use std::{fmt, iter::Peekable, str::Chars};
+
+use super::token::literal::quote;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Part {
+    Any,
+    One,
+    Char(char),
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Part {
+    fn matches(&self, ch: char) -> bool {
+        match self {
+            Part::Any | Part::One => true,
+            Part::Char(expected) => *expected == ch,
+            Part::Class { negated, ranges } => {
+                ranges.iter().any(|&(lo, hi)| lo <= ch && ch <= hi) != *negated
+            }
+        }
+    }
+}
+
+/// A compiled `like` or glob pattern. Both support `\` to escape the next
+/// char; `like` uses `%` and `_`, globs use `*`, `?` and `[...]` classes.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Wildcard {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl Wildcard {
+    pub fn like(source: &str) -> Self {
+        let mut chars = source.chars();
+        let mut parts = Vec::new();
+
+        while let Some(ch) = chars.next() {
+            parts.push(match ch {
+                '%' => Part::Any,
+                '_' => Part::One,
+                '\\' => Part::Char(chars.next().unwrap_or('\\')),
+                _ => Part::Char(ch),
+            });
+        }
+
+        Self {
+            source: source.to_string(),
+            parts,
+        }
+    }
+
+    pub fn glob(source: &str) -> Self {
+        let mut chars = source.chars().peekable();
+        let mut parts = Vec::new();
+
+        while let Some(ch) = chars.next() {
+            parts.push(match ch {
+                '*' => Part::Any,
+                '?' => Part::One,
+                '\\' => Part::Char(chars.next().unwrap_or('\\')),
+                '[' => Self::class(&mut chars).unwrap_or(Part::Char('[')),
+                _ => Part::Char(ch),
+            });
+        }
+
+        Self {
+            source: source.to_string(),
+            parts,
+        }
+    }
+
+    /// Parses a `[...]` class after its opening bracket, leaving `chars`
+    /// untouched if the class is unterminated.
+    fn class(chars: &mut Peekable<Chars>) -> Option<Part> {
+        let mut lookahead = chars.clone();
+        let negated = lookahead.next_if(|&ch| ch == '!' || ch == '^').is_some();
+        let mut ranges = Vec::new();
+
+        while let Some(ch) = lookahead.next() {
+            if ch == ']' && !ranges.is_empty() {
+                *chars = lookahead;
+                return Some(Part::Class { negated, ranges });
+            }
+
+            let mut range = lookahead.clone();
+            let hi = match (range.next(), range.next()) {
+                (Some('-'), Some(hi)) if hi != ']' => {
+                    lookahead = range;
+                    hi
+                }
+                _ => ch,
+            };
+            ranges.push((ch, hi));
+        }
+
+        None
+    }
+
+    /// The pattern as written.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+        let (mut part, mut pos) = (0, 0);
+        let mut backtrack = None;
+
+        while pos < text.len() {
+            match self.parts.get(part) {
+                Some(Part::Any) => {
+                    backtrack = Some((part, pos));
+                    part += 1;
+                }
+                Some(expected) if expected.matches(text[pos]) => {
+                    part += 1;
+                    pos += 1;
+                }
+                _ => match backtrack {
+                    Some((any, start)) => {
+                        backtrack = Some((any, start + 1));
+                        part = any + 1;
+                        pos = start + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        self.parts[part..].iter().all(|rest| *rest == Part::Any)
+    }
+}
+
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct Regex(regex::Regex);
+
+#[cfg(feature = "regex")]
+impl Regex {
+    pub fn new(source: &str) -> Result<Self, regex::Error> {
+        regex::Regex::new(source).map(Regex)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Eq for Regex {}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Pattern {
+    Like(Wildcard),
+    Glob(Wildcard),
+    #[cfg(feature = "regex")]
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Pattern::Like(wildcard) | Pattern::Glob(wildcard) => wildcard.is_match(text),
+            #[cfg(feature = "regex")]
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Like(wildcard) => write!(f, "like {}", quote(wildcard.as_str())),
+            Pattern::Glob(wildcard) => write!(f, "glob {}", quote(wildcard.as_str())),
+            #[cfg(feature = "regex")]
+            Pattern::Regex(regex) => write!(f, "~ /{}/", regex.as_str().replace('/', "\\/")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn like() {
+        let pattern = Wildcard::like("val%");
+
+        assert!(pattern.is_match("value1"));
+        assert!(pattern.is_match("val"));
+        assert!(!pattern.is_match("eval"));
+
+        assert!(Wildcard::like("v_l%1").is_match("value1"));
+        assert!(!Wildcard::like("v_l%2").is_match("value1"));
+        assert!(Wildcard::like("%a%a%").is_match("banana"));
+        assert!(Wildcard::like(r"100\%").is_match("100%"));
+        assert!(!Wildcard::like(r"100\%").is_match("1000"));
+        assert!(Wildcard::like("").is_match(""));
+        assert!(!Wildcard::like("").is_match("a"));
+    }
+
+    #[test]
+    fn glob() {
+        assert!(Wildcard::glob("value*").is_match("value1"));
+        assert!(Wildcard::glob("value?").is_match("value1"));
+        assert!(!Wildcard::glob("value?").is_match("value12"));
+        assert!(Wildcard::glob("value[0-2]").is_match("value1"));
+        assert!(!Wildcard::glob("value[!0-2]").is_match("value1"));
+        assert!(Wildcard::glob("value[!0-2]").is_match("value3"));
+        assert!(Wildcard::glob("[]a]*").is_match("]x"));
+        assert!(Wildcard::glob("[a-]").is_match("-"));
+        assert!(Wildcard::glob("a[b").is_match("a[b"));
+        assert!(Wildcard::glob(r"\*").is_match("*"));
+        assert!(!Wildcard::glob(r"\*").is_match("a"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex() {
+        let pattern = Pattern::Regex(Regex::new(r"^value\d+$").unwrap());
+
+        assert!(pattern.is_match("value12"));
+        assert!(!pattern.is_match("value"));
+        assert!(Regex::new("(").is_err());
+    }
+}
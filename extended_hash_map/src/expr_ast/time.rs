@@ -0,0 +1,313 @@
+//! ISO-8601 dates, datetimes and durations. Points in time are kept as UTC
+//! nanoseconds since the Unix epoch, so they compare and add exactly.
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+const NANOS_PER_DAY: i128 = 86_400 * NANOS_PER_SEC;
+
+/// A `P[nY][nM][nW][nD][T[nH][nM][n[.f]S]]` duration. Years and months
+/// depend on the calendar, so they are kept apart from the exact part.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Duration {
+    pub months: i64,
+    pub nanos: i128,
+}
+
+impl Duration {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.strip_prefix('P')?;
+        let (date, time) = match text.split_once('T') {
+            Some((_, "")) => return None,
+            Some((date, time)) => (date, Some(time)),
+            None => (text, None),
+        };
+
+        let mut duration = Duration::default();
+        let mut empty = true;
+
+        for (part, units, is_time) in [(date, "YMWD", false), (time.unwrap_or(""), "HMS", true)] {
+            let mut units = units.chars();
+            let mut rest = part;
+
+            while !rest.is_empty() {
+                let end = rest.find(|ch: char| !ch.is_ascii_digit() && ch != '.')?;
+                let (amount, unit) = (&rest[..end], rest[end..].chars().next()?);
+                rest = &rest[end + unit.len_utf8()..];
+
+                // each unit appears at most once and in order
+                units.find(|&expected| expected == unit)?;
+                empty = false;
+
+                if unit == 'S' {
+                    duration.nanos = duration.nanos.checked_add(seconds(amount)?)?;
+                    continue;
+                }
+
+                let amount = amount.parse::<i64>().ok()?;
+                match (unit, is_time) {
+                    ('Y', _) => duration.months = amount.checked_mul(12)?,
+                    ('M', false) => duration.months = duration.months.checked_add(amount)?,
+                    (unit, _) => {
+                        let nanos = match unit {
+                            'W' => 7 * NANOS_PER_DAY,
+                            'D' => NANOS_PER_DAY,
+                            'H' => 3600 * NANOS_PER_SEC,
+                            _ => 60 * NANOS_PER_SEC,
+                        };
+                        duration.nanos = duration
+                            .nanos
+                            .checked_add(nanos.checked_mul(amount as i128)?)?;
+                    }
+                }
+            }
+        }
+
+        (!empty).then_some(duration)
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        Some(Self {
+            months: self.months.checked_add(rhs.months)?,
+            nanos: self.nanos.checked_add(rhs.nanos)?,
+        })
+    }
+
+    pub fn checked_mul(self, factor: i128) -> Option<Self> {
+        Some(Self {
+            months: self.months.checked_mul(factor.try_into().ok()?)?,
+            nanos: self.nanos.checked_mul(factor)?,
+        })
+    }
+
+    /// The opposite duration, or `None` if a part is the minimum.
+    pub fn checked_neg(self) -> Option<Self> {
+        Some(Self {
+            months: self.months.checked_neg()?,
+            nanos: self.nanos.checked_neg()?,
+        })
+    }
+}
+
+/// Parses `YYYY-MM-DD[THH:MM[:SS[.f]][Z|±HH[:MM]]]` into nanoseconds since
+/// the epoch. Datetimes without an offset are taken as UTC.
+pub fn parse_datetime(text: &str) -> Option<i128> {
+    let date = text.get(..10)?;
+    let (year, month, day) = (
+        digits(date.get(..4)?)?,
+        digits(date.get(5..7)?)?,
+        digits(date.get(8..)?)?,
+    );
+
+    if date.as_bytes()[4] != b'-'
+        || date.as_bytes()[7] != b'-'
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+    {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day)? as i128;
+    let time = match &text[10..] {
+        "" => return Some(days * NANOS_PER_DAY),
+        time => time.strip_prefix('T')?,
+    };
+
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(at) => (&time[..at], offset(&time[at..])?),
+        None => (time, 0),
+    };
+    let (hour, rest) = time.split_once(':')?;
+    let (minute, second) = rest.split_once(':').unwrap_or((rest, "0"));
+    let (hour, minute) = (two_digits(hour)?, two_digits(minute)?);
+    let second = seconds(second).filter(|&nanos| nanos < 60 * NANOS_PER_SEC)?;
+
+    if hour >= 24 || minute >= 60 {
+        return None;
+    }
+
+    Some(
+        days * NANOS_PER_DAY
+            + (hour * 3600 + minute * 60 - offset) as i128 * NANOS_PER_SEC
+            + second,
+    )
+}
+
+/// Shifts a point in time by whole calendar months, clamping the day to
+/// the length of the target month, or `None` past the representable days.
+pub fn add_months(time: i128, months: i64) -> Option<i128> {
+    let (days, nanos) = (
+        time.div_euclid(NANOS_PER_DAY),
+        time.rem_euclid(NANOS_PER_DAY),
+    );
+    let (year, month, day) = civil_from_days(days.try_into().ok()?)?;
+
+    let total = (year * 12 + month - 1).checked_add(months)?;
+    let (year, month) = (total.div_euclid(12), total.rem_euclid(12) + 1);
+    let day = day.min(days_in_month(year, month));
+
+    Some(days_from_civil(year, month, day)? as i128 * NANOS_PER_DAY + nanos)
+}
+
+/// Shifts a point in time by a duration, months first.
+pub fn shift(time: i128, duration: Duration) -> Option<i128> {
+    add_months(time, duration.months)?.checked_add(duration.nanos)
+}
+
+/// An offset `Z`, `±HH`, `±HHMM` or `±HH:MM` in seconds.
+fn offset(text: &str) -> Option<i64> {
+    let (sign, text) = match text.split_at(1) {
+        ("Z", "") => return Some(0),
+        ("+", text) => (1, text),
+        ("-", text) => (-1, text),
+        _ => return None,
+    };
+    let (hours, minutes) = match (text.len(), text.split_once(':')) {
+        (_, Some((hours, minutes))) => (hours, minutes),
+        (2, None) => (text, "00"),
+        (4, None) => (text.get(..2)?, text.get(2..)?),
+        _ => return None,
+    };
+
+    match (two_digits(hours)?, two_digits(minutes)?) {
+        (hours, minutes) if hours < 24 && minutes < 60 => {
+            Some(sign * (hours * 3600 + minutes * 60))
+        }
+        _ => None,
+    }
+}
+
+/// Parses `SS[.f]` into nanoseconds, ignoring digits beyond nanoseconds.
+fn seconds(text: &str) -> Option<i128> {
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let fraction = format!("{:0<9.9}", fraction);
+
+    if whole.is_empty() || !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(digits(whole)? as i128 * NANOS_PER_SEC + fraction.parse::<i128>().ok()?)
+}
+
+fn two_digits(text: &str) -> Option<i64> {
+    (text.len() == 2).then(|| digits(text))?
+}
+
+fn digits(text: &str) -> Option<i64> {
+    match text.bytes().all(|byte| byte.is_ascii_digit()) {
+        true => text.parse().ok(),
+        false => None,
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since the epoch of a proleptic Gregorian date, if they fit.
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era.checked_mul(146_097)?.checked_add(day_of_era - 719_468)
+}
+
+/// The proleptic Gregorian date of days since the epoch, if its year fits.
+fn civil_from_days(days: i64) -> Option<(i64, i64, i64)> {
+    let days = days.checked_add(719_468)?;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+
+    Some((era * 400 + year_of_era + (month <= 2) as i64, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datetime() {
+        assert_eq!(parse_datetime("1970-01-01"), Some(0));
+        assert_eq!(parse_datetime("1970-01-02"), Some(NANOS_PER_DAY));
+        assert_eq!(
+            parse_datetime("2024-01-05T10:30"),
+            parse_datetime("2024-01-05T12:30:00+02:00")
+        );
+        assert_eq!(
+            parse_datetime("2000-03-01T00:00:00.5Z"),
+            Some(951_868_800 * NANOS_PER_SEC + NANOS_PER_SEC / 2)
+        );
+        assert_eq!(parse_datetime("1969-12-31T23:59:59Z"), Some(-NANOS_PER_SEC));
+        assert_eq!(parse_datetime("2024-02-29"), Some(19_782 * NANOS_PER_DAY));
+        assert_eq!(parse_datetime("2023-02-29"), None);
+        assert_eq!(parse_datetime("2024-13-01"), None);
+        assert_eq!(parse_datetime("2024-01-05T24:00"), None);
+        assert_eq!(parse_datetime("2024-01-05T10"), None);
+        assert_eq!(parse_datetime("2024-01-05T10:00+2"), None);
+        assert_eq!(parse_datetime("2024-01-05T10:00+1é1"), None);
+        assert_eq!(parse_datetime("2024-01-05T10:00+é11"), None);
+    }
+
+    #[test]
+    fn duration() {
+        let day = NANOS_PER_DAY;
+
+        assert_eq!(
+            Duration::parse("P1Y2M3D"),
+            Some(Duration {
+                months: 14,
+                nanos: 3 * day
+            })
+        );
+        assert_eq!(
+            Duration::parse("PT1H30M"),
+            Some(Duration {
+                months: 0,
+                nanos: 90 * 60 * NANOS_PER_SEC
+            })
+        );
+        assert_eq!(
+            Duration::parse("P2WT0.25S").map(|duration| duration.nanos),
+            Some(14 * day + NANOS_PER_SEC / 4)
+        );
+        assert_eq!(Duration::parse("P"), None);
+        assert_eq!(Duration::parse("P1DT"), None);
+        assert_eq!(Duration::parse("P1D1Y"), None);
+        assert_eq!(Duration::parse("P1H"), None);
+        assert_eq!(Duration::parse("P1.5D"), None);
+    }
+
+    #[test]
+    fn months() {
+        let time = |text| parse_datetime(text).unwrap();
+
+        assert_eq!(add_months(time("2024-01-31"), 1), Some(time("2024-02-29")));
+        assert_eq!(
+            add_months(time("2024-03-15T08:00"), -3),
+            Some(time("2023-12-15T08:00"))
+        );
+        assert_eq!(add_months(time("2024-02-29"), 12), Some(time("2025-02-28")));
+        assert_eq!(add_months(i64::MAX as i128 * NANOS_PER_DAY, 1), None);
+        assert_eq!(add_months(0, i64::MAX), None);
+        assert_eq!(
+            Duration {
+                months: i64::MIN,
+                nanos: 0
+            }
+            .checked_neg(),
+            None
+        );
+    }
+}
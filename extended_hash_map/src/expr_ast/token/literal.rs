@@ -1,5 +1,59 @@
-#[derive(Debug, PartialEq, Eq)]
+use std::fmt;
+
+/// A literal as written, normalized by the lexer: numbers in decimal and
+/// strings unquoted.
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Literal {
     Integer(String),
     Float(String),
+    String(String),
+    Bool(bool),
+    DateTime(String),
+    Duration(String),
+    Null, // an empty key component
+    NaN,
+}
+
+impl Literal {
+    /// A float as the lexer writes it, with a fraction or exponent.
+    /// Infinities are written as numbers too large to hold, which parse
+    /// back to them.
+    pub(crate) fn float(float: f64) -> Literal {
+        match float {
+            _ if float.is_nan() => Literal::NaN,
+            _ if float == f64::INFINITY => Literal::Float(String::from("1e999")),
+            _ if float == f64::NEG_INFINITY => Literal::Float(String::from("-1e999")),
+            _ => Literal::Float(format!("{:?}", float)),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Integer(text)
+            | Literal::Float(text)
+            | Literal::DateTime(text)
+            | Literal::Duration(text) => f.write_str(text),
+            Literal::String(text) => f.write_str(&quote(text)),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::Null => f.write_str("null"),
+            Literal::NaN => f.write_str("nan"),
+        }
+    }
+}
+
+/// Single-quotes `text`, escaping backslashes and quotes.
+pub(crate) fn quote(text: &str) -> String {
+    let mut quoted = String::from("'");
+
+    for ch in text.chars() {
+        if ch == '\\' || ch == '\'' {
+            quoted.push('\\');
+        }
+        quoted.push(ch);
+    }
+    quoted.push('\'');
+
+    quoted
 }
@@ -2,12 +2,35 @@ pub mod literal;
 pub mod op;
 
 use literal::Literal;
-use op::OpKind;
+use op::{ArithOp, OpKind};
 
-#[derive(Debug, PartialEq, Eq)]
+/// A byte range `start..end` of the lexed text.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Token {
     Literal(Literal),
     Op(OpKind),
+    Ident(String),
+    Position(usize),
+    Arith(ArithOp),
+    Approx,
+    PlusMinus,
+    Tilde,
+    Regex(String),
+    LParen,
+    RParen,
+    Dot,
     Delim,
     Eof,
 }
@@ -21,17 +44,14 @@ impl Token {
         *self == Token::Delim
     }
 
-    pub fn is_op(&self) -> bool {
-        match *self {
-            Token::Op(_) => true,
-            _ => false,
-        }
+    pub fn is_comparison(&self) -> bool {
+        matches!(*self, Token::Op(_) | Token::Approx | Token::Tilde)
+            || self.is_keyword("like")
+            || self.is_keyword("glob")
+            || self.is_keyword("is")
     }
 
-    pub fn is_literal(&self) -> bool {
-        match *self {
-            Token::Literal(_) => true,
-            _ => false,
-        }
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(*self, Token::Ident(ref ident) if ident.eq_ignore_ascii_case(keyword))
     }
 }
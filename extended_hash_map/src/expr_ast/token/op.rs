@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum OpKind {
     Eq, // =
@@ -7,3 +9,46 @@ pub enum OpKind {
     Gt, // >
     Ge, // >=
 }
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ArithOp {
+    Add, // +
+    Sub, // -
+    Mul, // *
+    Div, // /
+    Rem, // %
+}
+
+impl ArithOp {
+    pub fn precedence(&self) -> u8 {
+        match self {
+            ArithOp::Add | ArithOp::Sub => 1,
+            ArithOp::Mul | ArithOp::Div | ArithOp::Rem => 2,
+        }
+    }
+}
+
+impl fmt::Display for OpKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OpKind::Eq => "=",
+            OpKind::Ne => "<>",
+            OpKind::Lt => "<",
+            OpKind::Le => "<=",
+            OpKind::Gt => ">",
+            OpKind::Ge => ">=",
+        })
+    }
+}
+
+impl fmt::Display for ArithOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+            ArithOp::Rem => "%",
+        })
+    }
+}
@@ -0,0 +1,227 @@
+//! Traversal of parsed trees. A `Visitor` walks nodes by reference, a
+//! `Fold` consumes them and rebuilds a rewritten tree. Overridden methods
+//! call `walk_*` or `rebuild_*` to carry on into the children.
+
+use super::{
+    node::{ApproxEq, BinOp, Component, Expr, Is, Match, Node, Tree},
+    pattern::Pattern,
+    token::literal::Literal,
+};
+
+pub trait Visitor {
+    fn visit_tree(&mut self, tree: &Tree) {
+        for node in &tree.root {
+            self.visit_node(node);
+        }
+    }
+
+    fn visit_node(&mut self, node: &Node) {
+        walk_node(self, node);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_literal(&mut self, _lit: &Literal) {}
+
+    fn visit_component(&mut self, _component: &Component) {}
+
+    fn visit_pattern(&mut self, _pattern: &Pattern) {}
+}
+
+/// Visits the children of `node`.
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    match node {
+        Node::Literal(lit) => visitor.visit_literal(lit),
+        Node::Tuple(components) => {
+            for component in components {
+                visitor.visit_node(component);
+            }
+        }
+        Node::BinOp(BinOp { lhs, rhs, .. }) => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        Node::ApproxEq(ApproxEq {
+            lhs,
+            rhs,
+            tolerance,
+        }) => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+            if let Some(tolerance) = tolerance {
+                visitor.visit_expr(tolerance);
+            }
+        }
+        Node::Match(Match { expr, pattern }) => {
+            visitor.visit_expr(expr);
+            visitor.visit_pattern(pattern);
+        }
+        Node::Is(Is { expr, .. }) => visitor.visit_expr(expr),
+    }
+}
+
+/// Visits the children of `expr`.
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Literal(lit) => visitor.visit_literal(lit),
+        Expr::Component(component) => visitor.visit_component(component),
+        Expr::Tuple(items) => {
+            for item in items {
+                visitor.visit_expr(item);
+            }
+        }
+        Expr::Unary { expr, .. } => visitor.visit_expr(expr),
+        Expr::Binary { lhs, rhs, .. } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+    }
+}
+
+pub trait Fold {
+    /// Folds every node, keeping the spans of the source. Their parts
+    /// still follow the nodes as parsed, whatever shape they fold to.
+    fn fold_tree(&mut self, tree: Tree) -> Tree {
+        Tree {
+            root: tree
+                .root
+                .into_iter()
+                .map(|node| self.fold_node(node))
+                .collect(),
+            spans: tree.spans,
+        }
+    }
+
+    fn fold_node(&mut self, node: Node) -> Node {
+        rebuild_node(self, node)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        rebuild_expr(self, expr)
+    }
+
+    fn fold_literal(&mut self, lit: Literal) -> Literal {
+        lit
+    }
+
+    fn fold_component(&mut self, component: Component) -> Component {
+        component
+    }
+
+    fn fold_pattern(&mut self, pattern: Pattern) -> Pattern {
+        pattern
+    }
+}
+
+/// Rebuilds `node` from its folded children.
+pub fn rebuild_node<F: Fold + ?Sized>(folder: &mut F, node: Node) -> Node {
+    match node {
+        Node::Literal(lit) => Node::Literal(folder.fold_literal(lit)),
+        Node::Tuple(components) => Node::Tuple(
+            components
+                .into_iter()
+                .map(|component| folder.fold_node(component))
+                .collect(),
+        ),
+        Node::BinOp(BinOp { lhs, op, rhs }) => Node::BinOp(BinOp {
+            lhs: folder.fold_expr(lhs),
+            op,
+            rhs: folder.fold_expr(rhs),
+        }),
+        Node::ApproxEq(ApproxEq {
+            lhs,
+            rhs,
+            tolerance,
+        }) => Node::ApproxEq(ApproxEq {
+            lhs: folder.fold_expr(lhs),
+            rhs: folder.fold_expr(rhs),
+            tolerance: tolerance.map(|tolerance| folder.fold_expr(tolerance)),
+        }),
+        Node::Match(Match { expr, pattern }) => Node::Match(Match {
+            expr: folder.fold_expr(expr),
+            pattern: folder.fold_pattern(pattern),
+        }),
+        Node::Is(Is {
+            expr,
+            negated,
+            kind,
+        }) => Node::Is(Is {
+            expr: folder.fold_expr(expr),
+            negated,
+            kind,
+        }),
+    }
+}
+
+/// Rebuilds `expr` from its folded children.
+pub fn rebuild_expr<F: Fold + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::Literal(lit) => Expr::Literal(folder.fold_literal(lit)),
+        Expr::Component(component) => Expr::Component(folder.fold_component(component)),
+        Expr::Tuple(items) => Expr::Tuple(
+            items
+                .into_iter()
+                .map(|item| folder.fold_expr(item))
+                .collect(),
+        ),
+        Expr::Unary { op, expr } => Expr::Unary {
+            op,
+            expr: Box::new(folder.fold_expr(*expr)),
+        },
+        Expr::Binary { lhs, op, rhs } => Expr::Binary {
+            lhs: Box::new(folder.fold_expr(*lhs)),
+            op,
+            rhs: Box::new(folder.fold_expr(*rhs)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr_ast::parser::Parser;
+
+    #[test]
+    fn visit() {
+        #[derive(Default)]
+        struct Positions(Vec<usize>);
+
+        impl Visitor for Positions {
+            fn visit_component(&mut self, component: &Component) {
+                if let Component::Position(position) = component {
+                    self.0.push(*position);
+                }
+            }
+        }
+
+        let tree = Parser::new()
+            .parse_condition(">= 1, abs($0 - $2) ~= 3 ± $3, like 'a%'")
+            .unwrap();
+        let mut positions = Positions::default();
+        positions.visit_tree(&tree);
+
+        assert_eq!(positions.0, [0, 0, 2, 3, 2]);
+    }
+
+    #[test]
+    fn fold() {
+        struct Shift(usize);
+
+        impl Fold for Shift {
+            fn fold_component(&mut self, component: Component) -> Component {
+                match component {
+                    Component::Position(position) => Component::Position(position + self.0),
+                    _ => component,
+                }
+            }
+        }
+
+        let mut parser = Parser::new();
+        let tree = Shift(1).fold_tree(parser.parse_condition("$0 + 1 > $1, is null").unwrap());
+
+        assert_eq!(tree.to_string(), "$1 + 1 > $2, $2 is null");
+        assert_eq!(tree.spans.len(), 2);
+    }
+}
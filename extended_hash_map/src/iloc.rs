@@ -1,24 +1,22 @@
 use std::{collections::HashMap, hash::Hash, ops::Index};
 
 #[derive(Debug)]
-pub struct Iloc<'a, K: 'a, V: 'a> {
-    pub(crate) map: Option<&'a HashMap<K, V>>,
+pub struct Iloc<K, V> {
+    pub(crate) map: HashMap<K, V>,
 }
 
-impl<'a, K, V> Index<usize> for Iloc<'a, K, V>
+impl<K, V> Index<usize> for Iloc<K, V>
 where
     K: Ord + Hash,
 {
     type Output = V;
 
     fn index(&self, index: usize) -> &Self::Output {
-        if let Some(map) = self.map {
-            let mut keys = map.keys().collect::<Vec<_>>();
-            keys.sort();
+        let mut keys = self.map.keys().collect::<Vec<_>>();
+        keys.sort();
 
-            if let Some(key) = keys.iter().nth(index) {
-                return map.get(key).unwrap();
-            }
+        if let Some(key) = keys.get(index) {
+            return &self.map[*key];
         }
         panic!("No key with index {} was found", index);
     }
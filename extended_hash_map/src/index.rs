@@ -0,0 +1,170 @@
+use super::expr_ast::{
+    eval::Value,
+    optimize::{Bound, Range},
+};
+use std::{cmp::Ordering, sync::OnceLock};
+
+/// A secondary index over one key component: the keys sorted by the value
+/// of that component. It is built on first use after the map changes.
+#[derive(Debug)]
+pub(crate) struct ComponentIndex<K> {
+    pub(crate) position: usize,
+    entries: OnceLock<Vec<(Value, K)>>,
+}
+
+impl<K> ComponentIndex<K> {
+    pub(crate) fn new(position: usize) -> Self {
+        Self {
+            position,
+            entries: OnceLock::new(),
+        }
+    }
+
+    /// Drops the entries, to be rebuilt on next use.
+    pub(crate) fn invalidate(&mut self) {
+        self.entries = OnceLock::new();
+    }
+
+    /// The entries, built by `build` if the index is not up to date.
+    pub(crate) fn entries(&self, build: impl FnOnce() -> Vec<(Value, K)>) -> &[(Value, K)] {
+        self.entries.get_or_init(|| {
+            let mut entries = build();
+            entries.sort_by(|lhs, rhs| order(&lhs.0, &rhs.0));
+            entries
+        })
+    }
+}
+
+/// The entries whose value may lie in `range`, or `None` if its bounds
+/// cannot be looked up. Values of other kinds never compare with the
+/// bounds, so only the run of the bounds' kind is searched.
+pub(crate) fn candidates<'e, K>(
+    entries: &'e [(Value, K)],
+    range: &Range,
+) -> Option<&'e [(Value, K)]> {
+    let bounds = [&range.lower, &range.upper];
+    let kinds = bounds
+        .iter()
+        .filter_map(|bound| bound.as_ref())
+        .map(|bound| indexed_kind(&bound.value))
+        .collect::<Option<Vec<_>>>()?;
+
+    let kind = *kinds.first()?;
+    if kinds.iter().any(|&other| other != kind) {
+        return Some(&[]);
+    }
+
+    let mut start = entries.partition_point(|(value, _)| rank(value) < kind);
+    let mut end = entries.partition_point(|(value, _)| rank(value) <= kind);
+
+    if let Some(Bound { value, inclusive }) = &range.lower {
+        start = start.max(
+            entries.partition_point(|(entry, _)| match order(entry, value) {
+                Ordering::Less => true,
+                Ordering::Equal => !inclusive,
+                Ordering::Greater => false,
+            }),
+        );
+    }
+    if let Some(Bound { value, inclusive }) = &range.upper {
+        end = end.min(
+            entries.partition_point(|(entry, _)| match order(entry, value) {
+                Ordering::Less => true,
+                Ordering::Equal => *inclusive,
+                Ordering::Greater => false,
+            }),
+        );
+    }
+
+    Some(&entries[start..end.max(start)])
+}
+
+/// The rank of a bound value whose kind the index order agrees with
+/// comparisons on, if it is one.
+fn indexed_kind(value: &Value) -> Option<u8> {
+    match value {
+        Value::Float(float) if float.is_nan() => None,
+        Value::Integer(_)
+        | Value::Float(_)
+        | Value::String(_)
+        | Value::Bool(_)
+        | Value::Time(_) => Some(rank(value)),
+        _ => None,
+    }
+}
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Integer(_) | Value::Float(_) => 0,
+        Value::String(_) => 1,
+        Value::Bool(_) => 2,
+        Value::Time(_) => 3,
+        Value::Duration(_) => 4,
+        Value::Tuple(_) => 5,
+        Value::Null => 6,
+    }
+}
+
+/// A total order over values: by kind, then as they compare, with NaN
+/// after every other number.
+fn order(lhs: &Value, rhs: &Value) -> Ordering {
+    let is_nan = |value: &Value| matches!(value, Value::Float(float) if float.is_nan());
+
+    rank(lhs).cmp(&rank(rhs)).then_with(|| match (lhs, rhs) {
+        (Value::Duration(lhs), Value::Duration(rhs)) => {
+            (lhs.months, lhs.nanos).cmp(&(rhs.months, rhs.nanos))
+        }
+        (Value::Tuple(lhs), Value::Tuple(rhs)) => lhs
+            .iter()
+            .zip(rhs)
+            .map(|(lhs, rhs)| order(lhs, rhs))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| lhs.len().cmp(&rhs.len())),
+        _ => lhs
+            .partial_cmp(rhs)
+            .unwrap_or_else(|| is_nan(lhs).cmp(&is_nan(rhs))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr_ast::{node::Tree, optimize::Step, Parser};
+
+    fn range(condition: &str) -> Range {
+        let tree: Tree = Parser::new().parse_condition(condition).unwrap();
+
+        match crate::expr_ast::optimize::Plan::new(&tree).steps.remove(0) {
+            Step::Range(range) => range,
+            step => panic!("{:?}", step),
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let index = ComponentIndex::new(0);
+        let entries = index.entries(|| {
+            vec![
+                (Value::Integer(5), "5"),
+                (Value::String("a".to_string()), "a"),
+                (Value::Float(f64::NAN), "nan"),
+                (Value::Float(2.5), "2.5"),
+                (Value::Integer(1), "1"),
+                (Value::Null, "null"),
+                (Value::Integer(3), "3"),
+            ]
+        });
+        let keys = |condition| {
+            candidates(entries, &range(condition))
+                .map(|entries| entries.iter().map(|(_, key)| *key).collect::<Vec<_>>())
+        };
+
+        assert_eq!(keys(">= 2"), Some(vec!["2.5", "3", "5", "nan"]));
+        assert_eq!(keys("$0 > 1, $0 <= 3"), Some(vec!["2.5", "3"]));
+        assert_eq!(keys("< 3"), Some(vec!["1", "2.5"]));
+        assert_eq!(keys("= 3.0"), Some(vec!["3"]));
+        assert_eq!(keys(">= 'a'"), Some(vec!["a"]));
+        assert_eq!(keys("$0 > 1, $0 < 'b'"), Some(vec![]));
+        assert_eq!(keys("> P1D"), None);
+    }
+}
@@ -1,6 +1,19 @@
+pub mod ast;
 mod expr_ast;
 mod iloc;
+mod index;
 pub mod iter;
 pub mod map;
+pub mod query;
+mod spatial;
 
+pub use expr_ast::{
+    builder::{Cond, Operand},
+    diagnostic::Diagnostic,
+    format::KeyFormat,
+    token::Span,
+    LexerErr, ParserErr,
+};
 pub use map::ExtendedHashMap;
+pub use query::Query;
+pub use spatial::Metric;
@@ -1,104 +1,205 @@
 use super::{
     expr_ast::{
-        node::{BinOp, Node, Tree},
-        token::{literal::Literal, op::OpKind},
-        Parser,
+        diagnostic::Diagnostic,
+        eval::{Scope, Value},
+        format::KeyFormat,
+        node::{Component, Expr, Node, Tree},
+        optimize::{Plan, Range, Step},
+        Parser, ParserErr,
     },
     iloc::Iloc,
+    index::{self, ComponentIndex},
     iter::*,
+    query::Query,
+    spatial::{KdTree, Metric, Point, SpatialIndex},
 };
 use std::{borrow::Borrow, collections::HashMap, fmt::Debug, hash::Hash, ops::Index};
 
+const DEFAULT_TOLERANCE: f64 = 1e-9;
+
 #[derive(Debug)]
-pub struct ExtendedHashMap<'a, K, V> {
-    map: HashMap<K, V>,
-    pub iloc: Iloc<'a, K, V>,
+pub struct ExtendedHashMap<K, V> {
+    pub iloc: Iloc<K, V>,
+    schema: Vec<String>,
+    tolerance: f64,
+    metric: Metric,
+    key_format: KeyFormat,
+    strict: bool,
+    indexes: Vec<ComponentIndex<K>>,
+    spatial: Option<SpatialIndex<K>>,
 }
 
-impl<'a, K: 'a, V: 'a> ExtendedHashMap<'a, K, V> {
+impl<K, V> ExtendedHashMap<K, V> {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Names the key components, so conditions can refer to them as in
+    /// `"y >= 5 and x < 10"`. Keys must then have exactly as many components.
+    pub fn set_schema<I, S>(&mut self, names: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.schema = names.into_iter().map(Into::into).collect();
+    }
+
+    pub fn schema(&self) -> &[String] {
+        &self.schema
+    }
+
+    /// Sets the absolute tolerance `~=` uses when a condition gives none.
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance;
+    }
+
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Sets how `nearest` and `within_radius` measure distances.
+    pub fn set_metric(&mut self, metric: Metric) {
+        self.metric = metric;
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    /// Sets how keys are split into components. Keys that do not parse
+    /// under a strict format are never matched.
+    pub fn set_key_format(&mut self, format: KeyFormat) {
+        self.key_format = format;
+        self.invalidate_indexes();
+    }
+
+    pub fn key_format(&self) -> &KeyFormat {
+        &self.key_format
+    }
+
+    /// Makes conditions reject unknown chars such as `&` instead of
+    /// skipping them as delimiters.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Indexes the keys by their component at `position`, so range
+    /// conditions on it such as `$0 >= 5, $0 < 10` only test the keys in
+    /// range. The index is built on first use and rebuilt after keys change.
+    pub fn create_index(&mut self, position: usize) {
+        if !self.is_indexed(position) {
+            self.indexes.push(ComponentIndex::new(position));
+        }
+    }
+
+    /// Removes the index on `position`, returning whether there was one.
+    pub fn drop_index(&mut self, position: usize) -> bool {
+        let len = self.indexes.len();
+        self.indexes.retain(|index| index.position != position);
+
+        self.indexes.len() != len
+    }
+
+    pub fn is_indexed(&self, position: usize) -> bool {
+        self.indexes.iter().any(|index| index.position == position)
+    }
+
+    /// Indexes keys of `dimensions` numeric components as points in a k-d
+    /// tree, so bounding boxes such as `<5, >=5, >=3`, `nearest` and
+    /// `within_radius` only visit the points near them. It replaces any
+    /// spatial index there was.
+    ///
+    /// Panics if `dimensions` is 0.
+    pub fn create_spatial_index(&mut self, dimensions: usize) {
+        assert!(
+            dimensions > 0,
+            "A spatial index needs at least one dimension"
+        );
+
+        self.spatial = Some(SpatialIndex::new(dimensions));
+    }
+
+    /// Removes the spatial index, returning whether there was one.
+    pub fn drop_spatial_index(&mut self) -> bool {
+        self.spatial.take().is_some()
+    }
+
+    /// The number of dimensions of the spatial index, if there is one.
+    pub fn spatial_dimensions(&self) -> Option<usize> {
+        self.spatial.as_ref().map(|index| index.dimensions)
+    }
+
+    fn invalidate_indexes(&mut self) {
+        self.indexes.iter_mut().for_each(ComponentIndex::invalidate);
+        self.spatial.iter_mut().for_each(SpatialIndex::invalidate);
+    }
 }
 
-impl<K, V> ExtendedHashMap<'_, K, V>
+impl<K, V> ExtendedHashMap<K, V>
 where
     K: Eq + Hash,
 {
     #[inline]
     pub fn insert(&mut self, k: K, v: V) -> Option<V> {
-        self.map.insert(k, v)
+        let old = self.iloc.map.insert(k, v);
+
+        if old.is_none() {
+            self.invalidate_indexes();
+        }
+        old
     }
 
     #[inline]
     pub fn capacity(&self) -> usize {
-        self.map.capacity()
+        self.iloc.map.capacity()
     }
 
     #[inline]
     pub fn len(&self) -> usize {
-        self.map.len()
+        self.iloc.map.len()
     }
 
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.map.is_empty()
+        self.iloc.map.is_empty()
     }
 
     pub fn clear(&mut self) {
-        self.map.clear()
-    }
-
-    fn tree_state(&self, tree: &Tree) -> bool {
-        for node in tree.root.iter() {
-            match node {
-                Node::BinOp(bin_op) => {
-                    let BinOp { lhs, op, rhs } = bin_op;
-                    let lhs: f64 = match lhs {
-                        Literal::Integer(s) | Literal::Float(s) => s.parse().unwrap(),
-                    };
-
-                    let rhs: f64 = match rhs {
-                        Literal::Integer(s) | Literal::Float(s) => s.parse().unwrap(),
-                    };
-
-                    return match op {
-                        OpKind::Eq => lhs == rhs,
-                        OpKind::Ne => lhs != rhs,
-                        OpKind::Ge => lhs >= rhs,
-                        OpKind::Gt => lhs > rhs,
-                        OpKind::Le => lhs <= rhs,
-                        OpKind::Lt => lhs < rhs,
-                    };
-                }
-                _ => return false,
-            }
-        }
-        false
-    }
-
-    fn mix_trees(&self, lhs: &mut Tree, rhs: Tree) {
-        let mut rhs = rhs;
-        for i in 0..lhs.len() {
-            match lhs.root[i] {
-                Node::BinOp(ref mut bin_op) => match rhs.root[i] {
-                    Node::Literal(ref mut lit) => {
-                        std::mem::swap(&mut bin_op.lhs, lit);
-                    }
-                    _ => panic!(),
-                },
-                _ => panic!(),
-            }
+        self.iloc.map.clear();
+        self.invalidate_indexes();
+    }
+
+    /// The number of components a key must have to be matched by `tree`:
+    /// the schema length if one is set, otherwise one past the highest
+    /// referenced position. A predicate without a left-hand side refers to
+    /// its own position and so counts towards it, while `$0 > 4, $0 < 6`
+    /// only needs one component.
+    fn arity(&self, tree: &Tree) -> usize {
+        if !self.schema.is_empty() {
+            return self.schema.len();
         }
+
+        tree.root
+            .iter()
+            .filter_map(Node::max_position)
+            // no key has `usize::MAX + 1` components, so `$18446744073709551615`
+            // still matches none
+            .map(|position| position.saturating_add(1))
+            .max()
+            .unwrap_or(0)
     }
 }
 
-impl<K, V> ExtendedHashMap<'_, K, V>
+impl<K, V> ExtendedHashMap<K, V>
 where
     K: Ord,
 {
     pub fn iter(&self) -> Iter<'_, K, V> {
-        Iter::from(self.map.iter())
+        Iter::from(self.iloc.map.iter())
     }
 
     pub fn values(&self) -> Values<'_, K, V> {
@@ -110,58 +211,310 @@ where
     }
 }
 
-impl<V> ExtendedHashMap<'_, &str, V>
+impl<'k, V> ExtendedHashMap<&'k str, V>
 where
     V: Clone + Debug,
 {
+    /// Selects the entries whose keys satisfy `statement`, or none if it
+    /// fails to parse.
     pub fn ploc(&self, statement: &str) -> HashMap<&str, V> {
+        self.try_ploc(statement).unwrap_or_default()
+    }
+
+    /// Like `ploc`, but reports why `statement` fails to parse.
+    pub fn try_ploc(&self, statement: &str) -> Result<HashMap<&str, V>, ParserErr> {
+        let mut parser = Parser::new();
+        parser.set_strict(self.strict);
+
+        Ok(self.ploc_tree(&parser.parse_condition(statement)?))
+    }
+
+    /// Selects the entries whose keys satisfy a compiled query.
+    pub fn select(&self, query: &Query) -> HashMap<&str, V> {
+        self.scan(self.arity(query.tree()), query.plan())
+    }
+
+    /// Like `ploc`, but with a condition already parsed or built, e.g. by
+    /// `Cond`.
+    pub fn ploc_tree(&self, tree: &Tree) -> HashMap<&str, V> {
+        self.scan(self.arity(tree), &Plan::new(tree))
+    }
+
+    /// Tests the keys of `arity` components against `plan`, unless it can
+    /// never hold. Only the keys an index narrows the plan to are tested,
+    /// if one applies.
+    fn scan(&self, arity: usize, plan: &Plan) -> HashMap<&str, V> {
         let mut parser = Parser::new();
         let mut new_hash_map = HashMap::new();
 
-        let mut tree = match parser.parse_condition(statement) {
-            Ok(tree) => tree,
-            Err(_) => return new_hash_map,
-        };
+        if !plan.satisfiable {
+            return new_hash_map;
+        }
+        parser.set_key_format(self.key_format.clone());
 
-        for (key, val) in self.iter() {
-            if key.chars().any(|ch| ch.is_alphabetic()) {
-                continue;
-            }
+        let keys = self
+            .candidates(arity, plan)
+            .unwrap_or_else(|| self.iloc.map.keys().copied().collect());
 
+        for key in keys {
             let key_tree = match parser.parse(key) {
                 Ok(key_tree) => key_tree,
                 Err(_) => continue,
             };
 
-            if tree.len() == key_tree.len() {
-                self.mix_trees(&mut tree, key_tree);
-
-                if self.tree_state(&tree) {
-                    new_hash_map.insert(key.clone(), val.clone());
-                }
+            if key_tree.len() == arity
+                && plan.holds(&Scope::new(&key_tree, &self.schema, self.tolerance))
+            {
+                new_hash_map.insert(key, self.iloc.map[key].clone());
             }
         }
 
         new_hash_map
     }
-}
 
-impl<K, V> Default for ExtendedHashMap<'_, K, V> {
-    #[inline]
-    fn default() -> Self {
-        let mut hash_map = Self {
-            map: HashMap::<K, V>::default(),
-            iloc: Iloc { map: None },
+    /// The keys the most selective index narrows `plan` to: the index
+    /// leaving the fewest keys to test.
+    fn candidates(&self, arity: usize, plan: &Plan) -> Option<Vec<&'k str>> {
+        let sorted = ranges(plan)
+            .filter_map(|range| {
+                let position = self.position(&range.expr)?;
+                let index = self
+                    .indexes
+                    .iter()
+                    .find(|index| index.position == position)?;
+
+                index::candidates(self.index_entries(index), range)
+            })
+            .min_by_key(|entries| entries.len());
+
+        match (sorted, self.spatial_candidates(arity, plan)) {
+            (Some(entries), Some(keys)) if keys.len() <= entries.len() => Some(keys),
+            (Some(entries), _) => Some(entries.iter().map(|(_, key)| *key).collect()),
+            (None, keys) => keys,
+        }
+    }
+
+    /// The keys of the spatial index in the box the ranges of `plan` bound
+    /// components to, with the keys it cannot place. Only keys of as many
+    /// components as the index has dimensions can be matched through it.
+    fn spatial_candidates(&self, arity: usize, plan: &Plan) -> Option<Vec<&'k str>> {
+        let index = self
+            .spatial
+            .as_ref()
+            .filter(|index| index.dimensions == arity)?;
+        let mut lower = vec![f64::NEG_INFINITY; arity];
+        let mut upper = vec![f64::INFINITY; arity];
+        let mut bounded = false;
+
+        for range in ranges(plan) {
+            let Some(position) = self
+                .position(&range.expr)
+                .filter(|&position| position < arity)
+            else {
+                continue;
+            };
+
+            if let Some(bound) = &range.lower {
+                lower[position] = lower[position].max(coordinate(&bound.value)?);
+            }
+            if let Some(bound) = &range.upper {
+                upper[position] = upper[position].min(coordinate(&bound.value)?);
+            }
+            bounded = true;
+        }
+
+        if !bounded {
+            return None;
+        }
+
+        let tree = self.spatial_tree(index);
+        let keys = tree
+            .within_box(&lower, &upper)
+            .into_iter()
+            .map(|(_, key)| key);
+
+        Some(keys.chain(&tree.others).copied().collect())
+    }
+
+    /// The position of the component `expr` is, if it is one.
+    fn position(&self, expr: &Expr) -> Option<usize> {
+        match expr {
+            Expr::Component(Component::Position(position)) => Some(*position),
+            Expr::Component(Component::Name(name)) => {
+                self.schema.iter().position(|field| field == name)
+            }
+            _ => None,
+        }
+    }
+
+    /// The entries of `index`, building them from keys with a component at
+    /// its position if need be.
+    fn index_entries<'a>(&'a self, index: &'a ComponentIndex<&'k str>) -> &'a [(Value, &'k str)] {
+        index.entries(|| {
+            let mut parser = Parser::new();
+            parser.set_key_format(self.key_format.clone());
+
+            self.iloc
+                .map
+                .keys()
+                .filter_map(|key| {
+                    let key_tree = parser.parse(key).ok()?;
+                    Some((Value::from_node(key_tree.root.get(index.position)?)?, *key))
+                })
+                .collect()
+        })
+    }
+
+    /// The tree of `index`, building it if need be.
+    fn spatial_tree<'a>(&'a self, index: &'a SpatialIndex<&'k str>) -> &'a KdTree<&'k str> {
+        index.tree(|| {
+            let (points, others) = self.points(index.dimensions);
+            KdTree::new(index.dimensions, points, others)
+        })
+    }
+
+    /// The keys of `dimensions` components as points, and those with a
+    /// component that is not a number.
+    fn points(&self, dimensions: usize) -> (Vec<Point<&'k str>>, Vec<&'k str>) {
+        let mut parser = Parser::new();
+        let mut points = Vec::new();
+        let mut others = Vec::new();
+
+        parser.set_key_format(self.key_format.clone());
+
+        for key in self.iloc.map.keys() {
+            let key_tree = match parser.parse(key) {
+                Ok(key_tree) if key_tree.len() == dimensions => key_tree,
+                _ => continue,
+            };
+            let coords = key_tree
+                .root
+                .iter()
+                .map(|node| coordinate(&Value::from_node(node)?))
+                .collect::<Option<_>>();
+
+            match coords {
+                Some(coords) => points.push((coords, *key)),
+                None => others.push(*key),
+            }
+        }
+
+        (points, others)
+    }
+
+    /// The `k` keys of numeric components nearest to `point`, nearest
+    /// first, by the map's metric. Keys as near are taken in order. A point
+    /// with a NaN or infinite coordinate has no nearest keys.
+    pub fn nearest(&self, point: &[f64], k: usize) -> Vec<(&str, V)> {
+        if !is_finite(point) {
+            return Vec::new();
+        }
+
+        let distance = |coords: &[f64]| self.metric.distance(coords, point);
+        let nearest: Vec<&'k str> = match self.spatial_index(point) {
+            Some(index) => self
+                .spatial_tree(index)
+                .nearest(point, k, |lhs, rhs| self.metric.distance(lhs, rhs))
+                .into_iter()
+                .map(|(_, key)| *key)
+                .collect(),
+            None => {
+                let mut points = self
+                    .points(point.len())
+                    .0
+                    .into_iter()
+                    .map(|(coords, key)| (distance(&coords), key))
+                    .collect::<Vec<_>>();
+
+                points.sort_by(|lhs, rhs| lhs.0.total_cmp(&rhs.0).then(lhs.1.cmp(rhs.1)));
+                points.into_iter().take(k).map(|(_, key)| key).collect()
+            }
         };
 
-        hash_map.iloc.map =
-            unsafe { Some(std::ptr::NonNull::new_unchecked(&mut hash_map.map).as_ref()) };
+        nearest
+            .into_iter()
+            .map(|key| (key, self.iloc.map[key].clone()))
+            .collect()
+    }
+
+    /// Selects the keys of numeric components at most `radius` from
+    /// `point` by the map's metric, none if `point` has a NaN or infinite
+    /// coordinate.
+    pub fn within_radius(&self, point: &[f64], radius: f64) -> HashMap<&str, V> {
+        if !is_finite(point) {
+            return HashMap::new();
+        }
+
+        let within = |coords: &[f64]| self.metric.distance(coords, point) <= radius;
+        let keys: Vec<&'k str> = match self.spatial_index(point) {
+            Some(index) => {
+                // no coordinate of a point within the radius differs by more
+                let lower = point.iter().map(|coord| coord - radius).collect::<Vec<_>>();
+                let upper = point.iter().map(|coord| coord + radius).collect::<Vec<_>>();
+                let tree = self.spatial_tree(index);
 
-        hash_map
+                tree.within_box(&lower, &upper)
+                    .into_iter()
+                    .filter(|(coords, _)| within(coords))
+                    .map(|(_, key)| *key)
+                    .collect()
+            }
+            None => self
+                .points(point.len())
+                .0
+                .into_iter()
+                .filter(|(coords, _)| within(coords))
+                .map(|(_, key)| key)
+                .collect(),
+        };
+
+        keys.into_iter()
+            .map(|key| (key, self.iloc.map[key].clone()))
+            .collect()
+    }
+
+    /// The spatial index, if it has as many dimensions as `point`.
+    fn spatial_index(&self, point: &[f64]) -> Option<&SpatialIndex<&'k str>> {
+        self.spatial
+            .as_ref()
+            .filter(|index| index.dimensions == point.len())
+    }
+
+    /// Explains why `statement` fails to parse, pointing at where, or
+    /// `None` if it parses.
+    pub fn diagnose(&self, statement: &str) -> Option<Diagnostic> {
+        self.diagnose_all(statement).into_iter().next()
+    }
+
+    /// Explains every error in `statement`, going on past each one with
+    /// the next predicate.
+    pub fn diagnose_all(&self, statement: &str) -> Vec<Diagnostic> {
+        let mut parser = Parser::new();
+        parser.set_strict(self.strict);
+
+        parser.parse_condition_recovering(statement).1
+    }
+}
+
+impl<K, V> Default for ExtendedHashMap<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            iloc: Iloc {
+                map: HashMap::default(),
+            },
+            schema: Vec::new(),
+            tolerance: DEFAULT_TOLERANCE,
+            metric: Metric::default(),
+            key_format: KeyFormat::default(),
+            strict: false,
+            indexes: Vec::new(),
+            spatial: None,
+        }
     }
 }
 
-impl<K, Q: ?Sized, V> Index<&Q> for ExtendedHashMap<'_, K, V>
+impl<K, Q: ?Sized, V> Index<&Q> for ExtendedHashMap<K, V>
 where
     K: Eq + Hash + Borrow<Q>,
     Q: Eq + Hash,
@@ -170,23 +523,47 @@ where
 
     #[inline]
     fn index(&self, key: &Q) -> &V {
-        &self.map[key]
+        &self.iloc.map[key]
     }
 }
 
-impl<K, V, const N: usize> From<[(K, V); N]> for ExtendedHashMap<'_, K, V>
+impl<K, V, const N: usize> From<[(K, V); N]> for ExtendedHashMap<K, V>
 where
     K: Eq + Hash,
 {
     fn from(arr: [(K, V); N]) -> Self {
-        let mut hash_map = Self {
-            map: HashMap::from(arr),
-            iloc: Iloc { map: None },
-        };
+        Self {
+            iloc: Iloc {
+                map: HashMap::from(arr),
+            },
+            schema: Vec::new(),
+            tolerance: DEFAULT_TOLERANCE,
+            metric: Metric::default(),
+            key_format: KeyFormat::default(),
+            strict: false,
+            indexes: Vec::new(),
+            spatial: None,
+        }
+    }
+}
+
+fn ranges(plan: &Plan) -> impl Iterator<Item = &Range> {
+    plan.steps.iter().filter_map(|step| match step {
+        Step::Range(range) => Some(range),
+        Step::Node(_) => None,
+    })
+}
 
-        hash_map.iloc.map =
-            unsafe { Some(std::ptr::NonNull::new_unchecked(&mut hash_map.map).as_ref()) };
+/// Whether every coordinate of `point` is finite. Distances from other
+/// points are NaN or infinite for all keys alike, so they order none.
+fn is_finite(point: &[f64]) -> bool {
+    point.iter().all(|coord| coord.is_finite())
+}
 
-        hash_map
+/// The value as a point coordinate, if it is a number other than NaN.
+fn coordinate(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(_) | Value::Float(_) => value.as_f64().filter(|coord| !coord.is_nan()),
+        _ => None,
     }
 }
@@ -0,0 +1,161 @@
+use super::expr_ast::{
+    builder::Cond,
+    eval::Value,
+    node::{Component, Expr, Node, Tree},
+    optimize::Plan,
+    parser,
+    token::literal::Literal,
+    visit::{self, Visitor},
+    Parser, ParserErr,
+};
+
+/// A condition parsed, checked and optimized once, to select from any
+/// number of maps with `ExtendedHashMap::select`.
+#[derive(Debug, Clone)]
+pub struct Query {
+    tree: Tree,
+    plan: Plan,
+}
+
+impl Query {
+    /// Parses `statement` as `ploc` does.
+    pub fn compile(statement: &str) -> Result<Self, ParserErr> {
+        Self::from_tree(Parser::new().parse_condition(statement)?)
+    }
+
+    /// Like `compile`, but rejecting chars that start no token as a strict
+    /// map does.
+    pub fn compile_strict(statement: &str) -> Result<Self, ParserErr> {
+        let mut parser = Parser::new();
+        parser.set_strict(true);
+
+        Self::from_tree(parser.parse_condition(statement)?)
+    }
+
+    /// Checks a parsed or built condition: every literal must hold a valid
+    /// value and, like every name and tuple, be written as text that parses
+    /// back to it. Only built conditions can fail this, e.g. with NaN, null,
+    /// a name such as `like` or an empty tuple.
+    pub fn from_tree(tree: Tree) -> Result<Self, ParserErr> {
+        struct Invalid(Option<String>);
+
+        impl Invalid {
+            fn report(&mut self, invalid: impl ToString) {
+                self.0.get_or_insert_with(|| invalid.to_string());
+            }
+        }
+
+        impl Visitor for Invalid {
+            fn visit_expr(&mut self, expr: &Expr) {
+                if matches!(expr, Expr::Tuple(items) if items.is_empty()) {
+                    self.report(expr);
+                }
+                visit::walk_expr(self, expr);
+            }
+
+            fn visit_literal(&mut self, lit: &Literal) {
+                let written = match lit {
+                    Literal::Float(text) => text
+                        .trim_start_matches('-')
+                        .starts_with(|ch: char| ch.is_ascii_digit()),
+                    Literal::Null | Literal::NaN => false,
+                    _ => true,
+                };
+                if !written || Value::from_literal(lit).is_none() {
+                    self.report(lit);
+                }
+            }
+
+            fn visit_component(&mut self, component: &Component) {
+                match component {
+                    Component::Name(name) if !parser::is_name(name) => self.report(name),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut invalid = Invalid(None);
+        invalid.visit_tree(&tree);
+
+        match invalid.0 {
+            Some(lit) => Err(ParserErr::InvalidToken(lit)),
+            None => Ok(Self {
+                plan: Plan::new(&tree),
+                tree,
+            }),
+        }
+    }
+
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    pub fn predicates(&self) -> &[Node] {
+        &self.tree.root
+    }
+
+    /// False if the query was proven to select nothing, e.g. `< 5, $0 >= 5`.
+    pub fn is_satisfiable(&self) -> bool {
+        self.plan.satisfiable
+    }
+
+    pub(crate) fn plan(&self) -> &Plan {
+        &self.plan
+    }
+}
+
+impl TryFrom<Cond> for Query {
+    type Error = ParserErr;
+
+    fn try_from(cond: Cond) -> Result<Self, ParserErr> {
+        Self::from_tree(cond.into_tree())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr_ast::LexerErr;
+
+    #[test]
+    fn compile() {
+        let query = Query::compile(">= 1, $1 + P1D > 2024-01-01").unwrap();
+
+        assert_eq!(query.predicates().len(), 2);
+        assert_eq!(query.tree().to_string(), "$0 >= 1, $1 + P1D > 2024-01-01");
+        assert_eq!(
+            Query::compile(">= 1, <").unwrap_err(),
+            ParserErr::TokenMismatch
+        );
+        assert!(Query::compile(">= 1 & < 2").is_ok());
+        assert!(Query::compile_strict(">= 1 & < 2").is_err());
+
+        assert!(query.is_satisfiable());
+        assert!(!Query::compile("< 5, $0 >= 5").unwrap().is_satisfiable());
+    }
+
+    #[test]
+    fn from_cond() {
+        assert!(Query::try_from(Cond::component(0).lt(Cond::datetime("2024-01-05"))).is_ok());
+        assert_eq!(
+            Query::try_from(Cond::component(0).lt(Cond::datetime("2024-13-05"))).unwrap_err(),
+            ParserErr::InvalidToken("2024-13-05".to_string())
+        );
+        assert!(Query::try_from(Cond::component(0).lt(i128::MAX)).is_ok());
+        assert_eq!(
+            Query::try_from(Cond::component(0).lt(i128::MAX as u128 + 1)).unwrap_err(),
+            ParserErr::InvalidToken("170141183460469231731687303715884105728".to_string())
+        );
+        assert_eq!(
+            Query::compile("< 170141183460469231731687303715884105728").unwrap_err(),
+            ParserErr::Lexer(LexerErr::InvalidNumber(2))
+        );
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync + Clone>() {}
+
+        assert_send_sync::<Query>();
+    }
+}
@@ -0,0 +1,305 @@
+use std::{cmp::Ordering, collections::BinaryHeap, sync::OnceLock};
+
+/// A spatial index over keys of `dimensions` components. It is built on
+/// first use after the map changes.
+#[derive(Debug)]
+pub(crate) struct SpatialIndex<K> {
+    pub(crate) dimensions: usize,
+    tree: OnceLock<KdTree<K>>,
+}
+
+impl<K> SpatialIndex<K> {
+    pub(crate) fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions,
+            tree: OnceLock::new(),
+        }
+    }
+
+    /// Drops the tree, to be rebuilt on next use.
+    pub(crate) fn invalidate(&mut self) {
+        self.tree = OnceLock::new();
+    }
+
+    /// The tree, built by `build` if the index is not up to date.
+    pub(crate) fn tree(&self, build: impl FnOnce() -> KdTree<K>) -> &KdTree<K> {
+        self.tree.get_or_init(build)
+    }
+}
+
+/// The coordinates of a key.
+pub(crate) type Point<K> = (Box<[f64]>, K);
+
+/// Points with their keys, split at the median of one coordinate per level
+/// in turn. Every subslice of `points` is a subtree with its root in the
+/// middle, smaller coordinates before it and greater ones after.
+#[derive(Debug)]
+pub(crate) struct KdTree<K> {
+    dimensions: usize,
+    points: Vec<Point<K>>,
+    /// Keys of as many components that are not all numbers, which have no
+    /// place in the tree.
+    pub(crate) others: Vec<K>,
+}
+
+impl<K> KdTree<K> {
+    pub(crate) fn new(dimensions: usize, mut points: Vec<Point<K>>, others: Vec<K>) -> Self {
+        split(&mut points, 0, dimensions);
+
+        Self {
+            dimensions,
+            points,
+            others,
+        }
+    }
+
+    /// The points between `lower` and `upper`, bounds included.
+    pub(crate) fn within_box(&self, lower: &[f64], upper: &[f64]) -> Vec<&Point<K>> {
+        let mut found = Vec::new();
+        self.search_box(&self.points, 0, lower, upper, &mut found);
+
+        found
+    }
+
+    /// The `k` points closest to `point`, nearest first and by key when as
+    /// near. `distance` must be at least the difference of any one
+    /// coordinate, so subtrees beyond the `k`th distance can be skipped.
+    pub(crate) fn nearest(
+        &self,
+        point: &[f64],
+        k: usize,
+        distance: impl Fn(&[f64], &[f64]) -> f64,
+    ) -> Vec<(f64, &K)>
+    where
+        K: Ord,
+    {
+        let mut heap = BinaryHeap::with_capacity(k.min(self.points.len()).saturating_add(1));
+
+        if k > 0 {
+            self.search_nearest(&self.points, 0, point, k, &distance, &mut heap);
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|neighbour| (neighbour.distance, neighbour.key))
+            .collect()
+    }
+
+    fn search_box<'a>(
+        &self,
+        points: &'a [Point<K>],
+        depth: usize,
+        lower: &[f64],
+        upper: &[f64],
+        found: &mut Vec<&'a Point<K>>,
+    ) {
+        if points.is_empty() {
+            return;
+        }
+
+        let axis = depth % self.dimensions;
+        let mid = points.len() / 2;
+        let (coords, _) = &points[mid];
+
+        if coords
+            .iter()
+            .zip(lower.iter().zip(upper))
+            .all(|(coord, (lower, upper))| lower <= coord && coord <= upper)
+        {
+            found.push(&points[mid]);
+        }
+        if lower[axis] <= coords[axis] {
+            self.search_box(&points[..mid], depth + 1, lower, upper, found);
+        }
+        if coords[axis] <= upper[axis] {
+            self.search_box(&points[mid + 1..], depth + 1, lower, upper, found);
+        }
+    }
+
+    fn search_nearest<'a>(
+        &self,
+        points: &'a [Point<K>],
+        depth: usize,
+        point: &[f64],
+        k: usize,
+        distance: &impl Fn(&[f64], &[f64]) -> f64,
+        heap: &mut BinaryHeap<Neighbour<'a, K>>,
+    ) where
+        K: Ord,
+    {
+        if points.is_empty() {
+            return;
+        }
+
+        let axis = depth % self.dimensions;
+        let mid = points.len() / 2;
+        let (coords, key) = &points[mid];
+
+        heap.push(Neighbour {
+            distance: distance(coords, point),
+            key,
+        });
+        if heap.len() > k {
+            heap.pop();
+        }
+
+        let gap = point[axis] - coords[axis];
+        let (near, far) = match gap < 0.0 {
+            true => (&points[..mid], &points[mid + 1..]),
+            false => (&points[mid + 1..], &points[..mid]),
+        };
+
+        self.search_nearest(near, depth + 1, point, k, distance, heap);
+        if heap.len() < k
+            || heap
+                .peek()
+                .is_some_and(|farthest| gap.abs() <= farthest.distance)
+        {
+            self.search_nearest(far, depth + 1, point, k, distance, heap);
+        }
+    }
+}
+
+/// Orders `points` into subtrees from `depth` on.
+fn split<K>(points: &mut [Point<K>], depth: usize, dimensions: usize) {
+    if points.len() <= 1 {
+        return;
+    }
+
+    let axis = depth % dimensions;
+    let mid = points.len() / 2;
+
+    points.select_nth_unstable_by(mid, |lhs, rhs| lhs.0[axis].total_cmp(&rhs.0[axis]));
+
+    let (before, after) = points.split_at_mut(mid);
+    split(before, depth + 1, dimensions);
+    split(&mut after[1..], depth + 1, dimensions);
+}
+
+/// How `nearest` and `within_radius` measure the distance between points.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Metric {
+    /// The length of the straight line between them.
+    #[default]
+    Euclidean,
+    /// The sum of the differences of their coordinates.
+    Manhattan,
+    /// The greatest difference of their coordinates.
+    Chebyshev,
+}
+
+impl Metric {
+    pub fn distance(self, lhs: &[f64], rhs: &[f64]) -> f64 {
+        let differences = lhs.iter().zip(rhs).map(|(lhs, rhs)| (lhs - rhs).abs());
+
+        match self {
+            Metric::Euclidean => differences
+                .map(|difference| difference.powi(2))
+                .sum::<f64>()
+                .sqrt(),
+            Metric::Manhattan => differences.sum(),
+            Metric::Chebyshev => differences.fold(0.0, f64::max),
+        }
+    }
+}
+
+/// A point found by a nearest-neighbour search, the farthest greatest.
+struct Neighbour<'a, K> {
+    distance: f64,
+    key: &'a K,
+}
+
+impl<K: Ord> Ord for Neighbour<'_, K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then_with(|| self.key.cmp(other.key))
+    }
+}
+
+impl<K: Ord> PartialOrd for Neighbour<'_, K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord> PartialEq for Neighbour<'_, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl<K: Ord> Eq for Neighbour<'_, K> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> KdTree<usize> {
+        let points = (0..400)
+            .map(|i| {
+                let coords = [(i % 20) as f64, (i / 20) as f64 * 0.5, (i % 7) as f64];
+                (Box::from(coords), i)
+            })
+            .collect();
+
+        KdTree::new(3, points, Vec::new())
+    }
+
+    #[test]
+    fn within_box() {
+        let tree = grid();
+        let (lower, upper) = ([2.0, 1.0, 3.0], [5.0, 2.5, f64::INFINITY]);
+        let mut found = tree
+            .within_box(&lower, &upper)
+            .into_iter()
+            .map(|(_, key)| *key)
+            .collect::<Vec<_>>();
+        found.sort();
+
+        let expected = tree
+            .points
+            .iter()
+            .filter(|(coords, _)| {
+                (0..3).all(|axis| lower[axis] <= coords[axis] && coords[axis] <= upper[axis])
+            })
+            .map(|(_, key)| *key)
+            .collect::<std::collections::BTreeSet<_>>();
+
+        assert!(!found.is_empty());
+        assert_eq!(found, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn nearest() {
+        let tree = grid();
+        let point = [7.3, 4.1, 2.0];
+
+        for metric in [Metric::Euclidean, Metric::Manhattan, Metric::Chebyshev] {
+            let distance = |lhs: &[f64], rhs: &[f64]| metric.distance(lhs, rhs);
+            let mut expected = tree
+                .points
+                .iter()
+                .map(|(coords, key)| (distance(coords, &point), key))
+                .collect::<Vec<_>>();
+            expected.sort_by(|lhs, rhs| lhs.0.total_cmp(&rhs.0).then(lhs.1.cmp(rhs.1)));
+            expected.truncate(10);
+
+            assert_eq!(tree.nearest(&point, 10, distance), expected);
+            assert!(tree.nearest(&point, 0, distance).is_empty());
+            assert_eq!(tree.nearest(&point, 1000, distance).len(), 400);
+            assert_eq!(tree.nearest(&point, 1 << 60, distance).len(), 400);
+            assert_eq!(tree.nearest(&point, usize::MAX, distance).len(), 400);
+        }
+    }
+
+    #[test]
+    fn metrics() {
+        let (lhs, rhs) = ([1.0, -2.0, 0.5], [4.0, 2.0, 0.5]);
+
+        assert_eq!(Metric::Euclidean.distance(&lhs, &rhs), 5.0);
+        assert_eq!(Metric::Manhattan.distance(&lhs, &rhs), 7.0);
+        assert_eq!(Metric::Chebyshev.distance(&lhs, &rhs), 4.0);
+        assert_eq!(Metric::default(), Metric::Euclidean);
+    }
+}
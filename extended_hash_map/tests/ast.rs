@@ -0,0 +1,119 @@
+use extended_hash_map::{
+    ast::{self, BinOp, Component, Expr, Literal, Node, OpKind, Visitor},
+    Cond, Query,
+};
+
+#[test]
+fn display() {
+    let display = |condition| ast::parse_condition(condition).unwrap().to_string();
+
+    assert_eq!(display(">= 1; <5"), "$0 >= 1, $1 < 5");
+    assert_eq!(display("x != 'it'"), "x <> 'it'");
+    assert_eq!(display("= 'a\\'b'"), "$0 = 'a\\'b'");
+    assert_eq!(display("(($0 + 1)) * 2 > 3"), "($0 + 1) * 2 > 3");
+    assert_eq!(display("$0 - ($1 - $2) = 0"), "$0 - ($1 - $2) = 0");
+    assert_eq!(display("$0 - $1 - $2 = 0"), "$0 - $1 - $2 = 0");
+    assert_eq!(display("= (1, )"), "$0 = (1,)");
+    assert_eq!(display("~= 1 +/- 0.5"), "$0 ~= 1 ± 0.5");
+    assert_eq!(display("IS NOT NULL"), "$0 is not null");
+    assert_eq!(display("$0.1 LIKE 'a%'"), "$0.1 like 'a%'");
+}
+
+#[test]
+fn round_trip() {
+    for condition in [
+        ">= 1, < 5",
+        "$0 + 2 * $1 >= -3.5e2",
+        "-$0 < - 1, - -$1 > 0, -(1 + $2) = abs(-$2)",
+        "(1, ($0, 'a')) = $1",
+        "x ~= $1 / 2 ± 0.1, y ~= 0",
+        "is true, $1 is not nan, is false",
+        "$0.1.2 like 'a\\_%', glob '[a-c]*\\'x'",
+        "> 2024-01-05T10:30:00+02:00, $0 - P1M < 2024-02-01",
+        "($0 % 2) * 3 <> 1 - (2 - 3)",
+    ] {
+        let tree = ast::parse_condition(condition).unwrap();
+        let text = tree.to_string();
+
+        assert_eq!(
+            ast::parse_condition(&text).unwrap().root,
+            tree.root,
+            "{} printed as {}",
+            condition,
+            text
+        );
+    }
+
+    // built conditions print the same way, or are rejected if they cannot
+    for cond in [
+        Cond::component(0).lt(f64::INFINITY),
+        Cond::name("null").gt(-f64::INFINITY * 2.0),
+        Cond::name("nan").eq_to(Cond::tuple([1.5])),
+    ] {
+        let tree = ast::Tree::from(cond);
+        let text = tree.to_string();
+
+        assert!(Query::from_tree(tree.clone()).is_ok(), "{}", text);
+        assert_eq!(
+            ast::parse_condition(&text).unwrap().root,
+            tree.root,
+            "{}",
+            text
+        );
+    }
+    for cond in [
+        Cond::component(0).eq_to(f64::NAN),
+        Cond::component(0).eq_to(Expr::Literal(Literal::Null)),
+        Cond::component(0).lt(Expr::Literal(Literal::Float("inf".to_string()))),
+        Cond::name("like").gt(1),
+        Cond::name("x y").gt(1),
+        Cond::component(0).eq_to(Cond::tuple(Vec::<i32>::new())),
+    ] {
+        assert!(Query::try_from(cond).is_err());
+    }
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn round_trip_regex() {
+    let tree = ast::parse_condition(r"~ /^a\/b\d$/").unwrap();
+
+    assert_eq!(tree.to_string(), r"$0 ~ /^a\/b\d$/");
+    assert_eq!(
+        ast::parse_condition(&tree.to_string()).unwrap().root,
+        tree.root
+    );
+}
+
+#[test]
+fn build() {
+    let tree = ast::Tree {
+        root: vec![Node::BinOp(BinOp {
+            lhs: Expr::Component(Component::Name("price".to_string())),
+            op: OpKind::Le,
+            rhs: Expr::Literal(Literal::Integer("10".to_string())),
+        })],
+        ..Default::default()
+    };
+
+    assert_eq!(tree.to_string(), "price <= 10");
+    assert_eq!(ast::parse_condition("price <= 10").unwrap().root, tree.root);
+}
+
+#[test]
+fn visit() {
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_component(&mut self, component: &Component) {
+            if let Component::Name(name) = component {
+                self.0.push(name.clone());
+            }
+        }
+    }
+
+    let mut names = Names(vec![]);
+    names.visit_tree(&ast::parse_condition("a > 1, abs(b - a) < 2").unwrap());
+
+    assert_eq!(names.0, ["a", "b", "a"]);
+}
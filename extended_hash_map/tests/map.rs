@@ -1,5 +1,5 @@
-use extended_hash_map::ExtendedHashMap;
-use std::collections::{hash_map, HashMap};
+use extended_hash_map::{Cond, ExtendedHashMap, KeyFormat, LexerErr, Metric, ParserErr, Query};
+use std::collections::HashMap;
 
 const TEST_DATA_LEN: usize = 12;
 const TEST_DATA: [(&str, i32); TEST_DATA_LEN] = [
@@ -40,9 +40,9 @@ fn hash_map_iter() -> std::vec::IntoIter<(&'static str, i32)> {
 
 #[test]
 fn create() {
-    assert_eq!(ExtendedHashMap::<&str, i32>::new().is_empty(), true);
+    assert!(ExtendedHashMap::<&str, i32>::new().is_empty());
 
-    assert_eq!(ExtendedHashMap::<&str, i32>::default().is_empty(), true);
+    assert!(ExtendedHashMap::<&str, i32>::default().is_empty());
 }
 
 #[test]
@@ -64,11 +64,11 @@ fn len() {
 fn is_empty() {
     let mut map = ExtendedHashMap::<&str, i32>::new();
 
-    assert_eq!(map.is_empty(), true);
+    assert!(map.is_empty());
 
     map.insert("1", 1);
 
-    assert_eq!(map.is_empty(), false);
+    assert!(!map.is_empty());
 }
 
 #[test]
@@ -89,11 +89,11 @@ fn clear() {
 fn iloc() {
     let map = ExtendedHashMap::from(TEST_DATA);
 
-    let mut sorted_test_data = TEST_DATA.clone();
+    let mut sorted_test_data = TEST_DATA;
     sorted_test_data.sort_by(|a, b| a.0.cmp(b.0));
 
-    for i in 0..TEST_DATA_LEN {
-        assert_eq!(map.iloc[i], sorted_test_data[i].1);
+    for (i, (_, val)) in sorted_test_data.iter().enumerate() {
+        assert_eq!(map.iloc[i], *val);
     }
 }
 
@@ -116,6 +116,724 @@ fn ploc() {
     }
 }
 
+#[test]
+fn ploc_with_schema() {
+    let mut map = ExtendedHashMap::from(TEST_DATA);
+    map.set_schema(["x", "y", "z"]);
+
+    assert_eq!(map.schema(), ["x", "y", "z"]);
+    assert_eq!(
+        map.ploc("y >= 5 and x < 10"),
+        HashMap::from([("(1, 5, 3)", 400), ("(5, 5, 4)", 500)])
+    );
+    assert_eq!(
+        map.ploc("z > 3, >= 5"),
+        HashMap::from([("(5, 5, 4)", 500), ("(10, 5, 5)", 600)])
+    );
+    assert!(map.ploc("w > 0").is_empty());
+}
+
+#[test]
+fn ploc_cross_component() {
+    let map = ExtendedHashMap::from(TEST_DATA);
+
+    assert_eq!(map.ploc("$0 < $1"), HashMap::from([("1, 5", 100)]));
+    assert_eq!(map.ploc("$0 == $1"), HashMap::from([("5, 5", 200)]));
+    assert_eq!(
+        map.ploc("$1 >= $2, $0 <> 1"),
+        HashMap::from([("(5, 5, 4)", 500), ("(10, 5, 5)", 600)])
+    );
+}
+
+#[test]
+fn ploc_arithmetic() {
+    let map = ExtendedHashMap::from(TEST_DATA);
+
+    assert_eq!(
+        map.ploc("$0 * 2 + 1 > 10, > 0"),
+        HashMap::from([("5, 5", 200), ("10, 5", 300)])
+    );
+    assert_eq!(
+        map.ploc("abs($1 - $0) <= 3, $2 % 2 = 0"),
+        HashMap::from([("(5, 5, 4)", 500)])
+    );
+}
+
+#[test]
+fn ploc_negative_keys() {
+    let map = ExtendedHashMap::from([
+        ("-3, 5", 1),
+        ("3, -5", 2),
+        ("(-1.5, -2)", 3),
+        ("-10", 4),
+        ("10", 5),
+    ]);
+
+    assert_eq!(map.ploc("< 0"), HashMap::from([("-10", 4)]));
+    assert_eq!(map.ploc("> -5"), HashMap::from([("10", 5)]));
+    assert_eq!(
+        map.ploc("<= 3, < 0"),
+        HashMap::from([("3, -5", 2), ("(-1.5, -2)", 3)])
+    );
+    assert_eq!(map.ploc("$0 - $1 = -8"), HashMap::from([("-3, 5", 1)]));
+    assert_eq!(
+        map.ploc("$0 = -1.5, >= -2"),
+        HashMap::from([("(-1.5, -2)", 3)])
+    );
+}
+
+#[test]
+fn ploc_number_formats() {
+    let map = ExtendedHashMap::from([
+        ("0x1F", 1),
+        ("1_000_000", 2),
+        ("2.5E6", 3),
+        (".5", 4),
+        ("0b101, 1e-3", 5),
+    ]);
+
+    assert_eq!(map.ploc("= 31"), HashMap::from([("0x1F", 1)]));
+    assert_eq!(
+        map.ploc(">= 1e6"),
+        HashMap::from([("1_000_000", 2), ("2.5E6", 3)])
+    );
+    assert_eq!(map.ploc("< 0x1"), HashMap::from([(".5", 4)]));
+    assert_eq!(
+        map.ploc("= 0o5, < .01"),
+        HashMap::from([("0b101, 1e-3", 5)])
+    );
+}
+
+#[test]
+fn ploc_exact_integers() {
+    let map = ExtendedHashMap::from([
+        ("9007199254740992", 1),
+        ("9007199254740993", 2),
+        ("9007199254740993.0", 3),
+    ]);
+
+    assert_eq!(
+        map.ploc("= 9007199254740993"),
+        HashMap::from([("9007199254740993", 2)])
+    );
+    assert_eq!(
+        map.ploc("> 9007199254740992"),
+        HashMap::from([("9007199254740993", 2)])
+    );
+    assert_eq!(
+        map.ploc("$0 % 2 = 1"),
+        HashMap::from([("9007199254740993", 2)])
+    );
+}
+
+#[test]
+fn ploc_approx_eq() {
+    let mut map = ExtendedHashMap::from([("0.30000000000000004", 1), ("0.31", 2), ("0.5", 3)]);
+
+    assert!(map.ploc("= 0.3").is_empty());
+    assert_eq!(
+        map.ploc("~= 0.3"),
+        HashMap::from([("0.30000000000000004", 1)])
+    );
+    assert_eq!(
+        map.ploc("~= 0.3 ± 0.05"),
+        HashMap::from([("0.30000000000000004", 1), ("0.31", 2)])
+    );
+
+    map.set_tolerance(0.02);
+
+    assert_eq!(map.tolerance(), 0.02);
+    assert_eq!(
+        map.ploc("~= 0.3"),
+        HashMap::from([("0.30000000000000004", 1), ("0.31", 2)])
+    );
+}
+
+#[test]
+fn ploc_strings() {
+    let map = ExtendedHashMap::from(TEST_DATA);
+
+    assert_eq!(map.ploc("= 'value2'"), HashMap::from([("value2", 2)]));
+    assert_eq!(
+        map.ploc("< \"value3\""),
+        HashMap::from([("value1", 1), ("value2", 2)])
+    );
+    assert_eq!(map.ploc("<> 'value2', > 'v'").len(), 0);
+
+    let mut map = ExtendedHashMap::from([
+        ("(eu, 5)", 1),
+        ("(us, 7)", 2),
+        ("(eu, 9)", 3),
+        ("(1, 5)", 4),
+    ]);
+    map.set_schema(["region", "n"]);
+
+    assert_eq!(
+        map.ploc("region = 'eu' and n > 6"),
+        HashMap::from([("(eu, 9)", 3)])
+    );
+    assert_eq!(
+        map.ploc("region <> 'eu'"),
+        HashMap::from([("(us, 7)", 2), ("(1, 5)", 4)])
+    );
+    assert_eq!(
+        map.ploc("region >= 'a'"),
+        HashMap::from([("(eu, 5)", 1), ("(us, 7)", 2), ("(eu, 9)", 3)])
+    );
+}
+
+#[test]
+fn ploc_patterns() {
+    let map = ExtendedHashMap::from(TEST_DATA);
+
+    assert_eq!(
+        map.ploc("like 'val%'"),
+        HashMap::from([("value1", 1), ("value2", 2), ("value3", 3)])
+    );
+    assert_eq!(map.ploc("like 'value_'").len(), 3);
+    assert_eq!(
+        map.ploc("glob 'value[!2]'"),
+        HashMap::from([("value1", 1), ("value3", 3)])
+    );
+    assert!(map.ploc("like '1%'").is_empty());
+}
+
+#[test]
+fn ploc_nulls() {
+    let map = ExtendedHashMap::from([
+        ("1, , 5", 1),
+        ("1, 2, 5", 2),
+        ("(1, NaN, 3)", 3),
+        ("1,,", 4),
+    ]);
+
+    assert_eq!(map.ploc("$1 is null"), HashMap::from([("1,,", 4)]));
+    assert_eq!(
+        map.ploc("$1 is null, $2 = 5"),
+        HashMap::from([("1, , 5", 1)])
+    );
+    assert_eq!(
+        map.ploc("$1 is not null, $2 >= 4"),
+        HashMap::from([("1, 2, 5", 2)])
+    );
+    assert_eq!(
+        map.ploc("$2 = 5"),
+        HashMap::from([("1, , 5", 1), ("1, 2, 5", 2)])
+    );
+    assert_eq!(
+        map.ploc("$1 is nan, $2 = 3"),
+        HashMap::from([("(1, NaN, 3)", 3)])
+    );
+    assert_eq!(map.ploc("$1 < 3, $2 > 0").len(), 1);
+}
+
+#[test]
+fn ploc_booleans() {
+    let mut map = ExtendedHashMap::from([
+        ("(1, true)", 1),
+        ("(2, false)", 2),
+        ("(3, TRUE)", 3),
+        ("(4, 1)", 4),
+    ]);
+
+    assert_eq!(
+        map.ploc(">= 1, = true"),
+        HashMap::from([("(1, true)", 1), ("(3, TRUE)", 3)])
+    );
+    assert_eq!(
+        map.ploc("> 1, is false"),
+        HashMap::from([("(2, false)", 2)])
+    );
+
+    map.set_schema(["id", "enabled"]);
+
+    assert_eq!(
+        map.ploc("enabled is not true"),
+        HashMap::from([("(2, false)", 2), ("(4, 1)", 4)])
+    );
+}
+
+#[test]
+fn ploc_dates() {
+    let map = ExtendedHashMap::from([
+        ("(2023-12-31, 1)", 1),
+        ("(2024-01-05, 3)", 2),
+        ("(2024-01-05T18:00, 4)", 3),
+        ("(2024-03-01, 5)", 4),
+    ]);
+
+    assert_eq!(
+        map.ploc("> 2024-01-01, >= 3"),
+        HashMap::from([
+            ("(2024-01-05, 3)", 2),
+            ("(2024-01-05T18:00, 4)", 3),
+            ("(2024-03-01, 5)", 4)
+        ])
+    );
+    assert_eq!(
+        map.ploc("$0 < 2024-01-05 + P1D, $0 >= 2024-01-05T12:00, $1 > 0"),
+        HashMap::from([("(2024-01-05T18:00, 4)", 3)])
+    );
+    assert_eq!(
+        map.ploc("$0 + P1M < 2024-02-05, 2024-03-01 - $0 > P30D, $1 > 0"),
+        HashMap::from([("(2023-12-31, 1)", 1)])
+    );
+
+    // out of range arithmetic holds for no key instead of overflowing
+    assert!(map
+        .ploc("abs(P0D - P9223372036854775807M - P1M) > P0D")
+        .is_empty());
+    assert!(map
+        .ploc("$0 + P9223372036854775000D + P1M > 1970-01-01, $1 > 0")
+        .is_empty());
+    assert!(map
+        .ploc("$0 - (P0D - P9223372036854775807M - P1M) > 1970-01-01, $1 > 0")
+        .is_empty());
+    assert!(
+        Query::try_from(Cond::component(0).lt(Cond::datetime("2024-01-05T10:00+1é1"))).is_err()
+    );
+}
+
+#[test]
+fn ploc_invalid_dates() {
+    // not valid dates, so keys of three numbers as before dates were parsed
+    let map = ExtendedHashMap::from([("1000-20-30", 1), ("2024-13-05", 2), ("2024-01-05", 3)]);
+
+    assert_eq!(
+        map.ploc("> 0, > 0, > 0"),
+        HashMap::from([("1000-20-30", 1), ("2024-13-05", 2)])
+    );
+    assert_eq!(
+        map.ploc("= 2024, = 13, = 5"),
+        HashMap::from([("2024-13-05", 2)])
+    );
+    assert_eq!(map.ploc("= 2024-01-05"), HashMap::from([("2024-01-05", 3)]));
+}
+
+#[test]
+fn ploc_key_format() {
+    let mut map = ExtendedHashMap::from([
+        ("1-5", 1),
+        ("1/5", 2),
+        ("1;5", 3),
+        ("[2;7]", 4),
+        ("[2;7", 5),
+    ]);
+
+    assert_eq!(map.ploc("= 1, = 5").len(), 3);
+
+    map.set_key_format(KeyFormat::strict(";").with_brackets(&[('[', ']')]));
+
+    assert_eq!(
+        map.ploc(">= 1, >= 5"),
+        HashMap::from([("1;5", 3), ("[2;7]", 4)])
+    );
+
+    map.set_key_format(KeyFormat::lenient().with_delimiters(";"));
+
+    assert_eq!(map.ploc(">= 1, >= 5"), HashMap::from([("1;5", 3)]));
+    assert_eq!(map.ploc("= '1-5'"), HashMap::from([("1-5", 1)]));
+}
+
+#[test]
+fn ploc_nested_tuples() {
+    let map = ExtendedHashMap::from([
+        ("((1, 2), 3)", 1),
+        ("((2, 1), 3)", 2),
+        ("(1, 2, 3)", 3),
+        ("((1, 2), (3, 4))", 4),
+    ]);
+
+    assert_eq!(
+        map.ploc("$0.1 > 1, $1 = 3"),
+        HashMap::from([("((1, 2), 3)", 1)])
+    );
+    assert_eq!(
+        map.ploc("= (2, 1), = 3"),
+        HashMap::from([("((2, 1), 3)", 2)])
+    );
+    assert_eq!(
+        map.ploc("$0 = (1, 2), $1 >= 3"),
+        HashMap::from([("((1, 2), 3)", 1)])
+    );
+    assert_eq!(
+        map.ploc("$0 = (1, 2), $1 > (3,)"),
+        HashMap::from([("((1, 2), (3, 4))", 4)])
+    );
+    assert_eq!(map.ploc("< 2, = 2, = 3"), HashMap::from([("(1, 2, 3)", 3)]));
+}
+
+#[test]
+fn ploc_tree() {
+    let mut map = ExtendedHashMap::from(TEST_DATA);
+    let (conditions, _) = ploc_conditions_for_test_data();
+
+    let built = [
+        Cond::component(0).ge(1),
+        Cond::component(0).lt(3),
+        Cond::component(0).gt(0).and(Cond::component(1).gt(0)),
+        Cond::component(0).ge(10).and(Cond::component(1).gt(0)),
+        Cond::component(0)
+            .lt(5)
+            .and(Cond::component(1).ge(5))
+            .and(Cond::component(2).ge(3)),
+    ];
+
+    for (condition, cond) in conditions.into_iter().zip(built) {
+        assert_eq!(map.ploc_tree(&cond.into()), map.ploc(condition));
+    }
+
+    map.set_schema(["x", "y", "z"]);
+
+    assert_eq!(
+        map.ploc_tree(
+            &(Cond::name("x") + Cond::name("z"))
+                .ge(15)
+                .and(Cond::name("y").eq_to(5))
+                .and(Cond::name("z").is_not_null())
+                .into()
+        ),
+        HashMap::from([("(10, 5, 5)", 600)])
+    );
+}
+
+#[test]
+fn select() {
+    let map = ExtendedHashMap::from(TEST_DATA);
+    let other = ExtendedHashMap::from([("4, 6", 1), ("0, 6", 2)]);
+    let (conditions, results) = ploc_conditions_for_test_data();
+
+    for (condition, result) in conditions.into_iter().zip(results) {
+        assert_eq!(map.select(&Query::compile(condition).unwrap()), result);
+    }
+
+    let query = Query::compile(">0, >0").unwrap();
+    let shared = std::thread::spawn({
+        let query = query.clone();
+        move || other.select(&query).len()
+    });
+
+    assert_eq!(map.select(&query).len(), 3);
+    assert_eq!(shared.join().unwrap(), 1);
+}
+
+#[test]
+fn ploc_arity() {
+    let map = ExtendedHashMap::from([("5, 1", 1), ("5", 2), ("5, 1, 0", 3)]);
+
+    assert_eq!(
+        map.ploc("$0 > 4, $0 < 6, $1 = 1"),
+        HashMap::from([("5, 1", 1)])
+    );
+    assert_eq!(
+        map.ploc("$0 = 5, $0 <> 4, $0 >= 5"),
+        HashMap::from([("5", 2)])
+    );
+    assert_eq!(
+        map.ploc("$0 = 5, = 1, $0 > 0"),
+        HashMap::from([("5, 1", 1)])
+    );
+    assert_eq!(
+        map.ploc("$0 = 5, > 0, >= 0"),
+        HashMap::from([("5, 1, 0", 3)])
+    );
+    assert_eq!(
+        map.try_ploc("$18446744073709551615 > 0"),
+        Ok(HashMap::new())
+    );
+}
+
+#[test]
+fn ploc_optimized() {
+    let map = ExtendedHashMap::from(TEST_DATA);
+
+    assert_eq!(
+        map.ploc("$0 > 1, $0 <= 2 + 3, >= 4"),
+        HashMap::from([("(5, 5, 4)", 500)])
+    );
+    assert_eq!(
+        map.ploc("$0 > 1, $0 <= 5, $0 >= 5, $2 >= 0"),
+        map.ploc("= 5, >= 0, >= 0")
+    );
+    assert_eq!(
+        map.ploc(">= 1 * 10, > 0, 2 > 1"),
+        HashMap::from([("10, 5", 300)])
+    );
+
+    let query = Query::compile("< 5, $0 >= 5").unwrap();
+    assert!(!query.is_satisfiable());
+    assert!(map.select(&query).is_empty());
+    assert!(map.ploc("> 0, 1 > 2").is_empty());
+
+    // constant `~=` holds within the map's tolerance, which a query lacks
+    let mut map = ExtendedHashMap::from([("1", 1)]);
+    assert_eq!(
+        map.ploc("> 0, 1 ~= 1.0000000001"),
+        HashMap::from([("1", 1)])
+    );
+    assert!(map.ploc("> 0, 1 ~= 1.2").is_empty());
+    assert!(map.ploc("> 0, 1 ~= 1.2 ± 0.1").is_empty());
+
+    map.set_tolerance(0.5);
+    let query = Query::compile("> 0, 1 ~= 1.2").unwrap();
+    assert!(query.is_satisfiable());
+    assert_eq!(map.select(&query), HashMap::from([("1", 1)]));
+    assert!(!Query::compile("> 0, 1 ~= 1.2 ± 0.1")
+        .unwrap()
+        .is_satisfiable());
+}
+
+#[test]
+fn ploc_indexed() {
+    let plain = ExtendedHashMap::from(TEST_DATA);
+    let mut map = ExtendedHashMap::from(TEST_DATA);
+    let (conditions, results) = ploc_conditions_for_test_data();
+
+    map.create_index(0);
+    map.create_index(1);
+    map.create_index(1);
+    assert!(map.is_indexed(1) && !map.is_indexed(2));
+
+    for (condition, result) in conditions.into_iter().zip(results) {
+        assert_eq!(map.ploc(condition), result);
+    }
+    for condition in [
+        "$0 > 1, $0 <= 5, $1 = 5",
+        "$1 >= 5, $0 <> 5",
+        "$0 < 'value3'",
+        "$0 >= 'value1', $0 < 1",
+        "$0 + 1 > 5",
+        "$0 ~= 5",
+        "= 5, = 5",
+    ] {
+        assert_eq!(map.ploc(condition), plain.ploc(condition), "{}", condition);
+    }
+
+    map.insert("7, 5", 700);
+    assert_eq!(
+        map.ploc("$0 > 5, $0 < 10, $1 = 5"),
+        HashMap::from([("7, 5", 700)])
+    );
+
+    map.set_schema(["x", "y", "z"]);
+    assert_eq!(
+        map.ploc("x >= 5, y = 5, z > 3"),
+        HashMap::from([("(5, 5, 4)", 500), ("(10, 5, 5)", 600)])
+    );
+
+    assert!(map.drop_index(0));
+    assert!(!map.drop_index(0));
+    map.clear();
+    map.insert("1, 5", 1);
+    assert_eq!(map.ploc("x = 1"), HashMap::new());
+    map.set_schema(Vec::<String>::new());
+    assert_eq!(map.ploc("<5, = 5"), HashMap::from([("1, 5", 1)]));
+}
+
+#[test]
+fn ploc_spatial() {
+    let mut plain = ExtendedHashMap::from(TEST_DATA);
+    let mut map = ExtendedHashMap::from(TEST_DATA);
+    let (conditions, results) = ploc_conditions_for_test_data();
+
+    map.create_spatial_index(3);
+    map.insert("(2, 'a', 9)", 0);
+    plain.insert("(2, 'a', 9)", 0);
+    assert_eq!(map.spatial_dimensions(), Some(3));
+
+    for (condition, result) in conditions.into_iter().zip(results) {
+        assert_eq!(map.ploc(condition), result);
+    }
+    assert_eq!(
+        map.ploc("$0 >= 2, $2 = 9"),
+        HashMap::from([("(2, 'a', 9)", 0)])
+    );
+    for condition in [
+        "$0 > 1, $1 <= 5, $2 < 5",
+        "$2 >= 5",
+        "$0 >= 2, $2 = 9",
+        "$2 > 3, $2 < 'a', $1 is not null",
+        "$0 < 5, >= 'a', >= 3",
+    ] {
+        assert_eq!(map.ploc(condition), plain.ploc(condition), "{}", condition);
+    }
+
+    map.create_index(0);
+    assert_eq!(
+        map.ploc("$0 >= 1, $0 <= 10, $2 >= 4"),
+        HashMap::from([("(5, 5, 4)", 500), ("(10, 5, 5)", 600), ("(2, 'a', 9)", 0)])
+    );
+
+    assert!(map.drop_spatial_index());
+    assert!(!map.drop_spatial_index());
+}
+
+#[test]
+fn nearest() {
+    let mut map = ExtendedHashMap::from(TEST_DATA);
+    map.insert("5.5, 'a'", 0);
+
+    fn keys<'a>(map: &'a ExtendedHashMap<&str, i32>, point: &[f64], k: usize) -> Vec<&'a str> {
+        map.nearest(point, k)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    assert_eq!(keys(&map, &[6.0, 4.0], 2), ["5, 5", "10, 5"]);
+    assert_eq!(keys(&map, &[1.7], 2), ["2", "1"]);
+    assert_eq!(keys(&map, &[2.5], 2), ["2", "3"]);
+    assert_eq!(map.nearest(&[4.0, 5.0, 4.0], 1), [("(5, 5, 4)", 500)]);
+    assert!(keys(&map, &[0.0; 4], 3).is_empty());
+
+    for dimensions in [1, 2] {
+        map.create_spatial_index(dimensions);
+
+        assert_eq!(keys(&map, &[6.0, 4.0], 2), ["5, 5", "10, 5"]);
+        assert_eq!(keys(&map, &[2.5], 2), ["2", "3"]);
+        assert_eq!(keys(&map, &[2.5], 0), Vec::<&str>::new());
+        assert_eq!(keys(&map, &[2.5], 5).len(), 3);
+        assert_eq!(keys(&map, &[2.5], 1 << 60).len(), 3);
+        assert_eq!(keys(&map, &[2.5], usize::MAX).len(), 3);
+    }
+
+    // the same with or without the index
+    for spatial in [true, false] {
+        if !spatial {
+            map.drop_spatial_index();
+        }
+
+        assert!(keys(&map, &[f64::NAN, 4.0], 2).is_empty());
+        assert!(keys(&map, &[f64::INFINITY, 4.0], 2).is_empty());
+        assert!(map.within_radius(&[f64::NAN, 4.0], 10.0).is_empty());
+        assert!(map
+            .within_radius(&[f64::NEG_INFINITY], f64::INFINITY)
+            .is_empty());
+    }
+}
+
+#[test]
+fn nearest_by_metric() {
+    let mut map = ExtendedHashMap::from([("0, 0", 0), ("3, 3", 1), ("0, 5", 2), ("4.5, 0", 3)]);
+
+    for spatial in [false, true] {
+        if spatial {
+            map.create_spatial_index(2);
+        }
+
+        map.set_metric(Metric::Euclidean);
+        assert_eq!(
+            map.nearest(&[0.0, 0.0], 4)[1..],
+            [("3, 3", 1), ("4.5, 0", 3), ("0, 5", 2)]
+        );
+        assert_eq!(
+            map.within_radius(&[0.0, 0.0], 4.5),
+            HashMap::from([("0, 0", 0), ("3, 3", 1), ("4.5, 0", 3)])
+        );
+
+        map.set_metric(Metric::Manhattan);
+        assert_eq!(
+            map.nearest(&[0.0, 0.0], 4)[1..],
+            [("4.5, 0", 3), ("0, 5", 2), ("3, 3", 1)]
+        );
+        assert_eq!(
+            map.within_radius(&[0.0, 0.0], 5.0),
+            HashMap::from([("0, 0", 0), ("0, 5", 2), ("4.5, 0", 3)])
+        );
+
+        map.set_metric(Metric::Chebyshev);
+        assert_eq!(
+            map.nearest(&[0.0, 0.0], 4)[1..],
+            [("3, 3", 1), ("4.5, 0", 3), ("0, 5", 2)]
+        );
+        assert_eq!(
+            map.within_radius(&[1.0, 1.0], 2.0),
+            HashMap::from([("0, 0", 0), ("3, 3", 1)])
+        );
+
+        assert!(map.within_radius(&[0.0, 0.0], -1.0).is_empty());
+        assert!(map.within_radius(&[0.0], 10.0).is_empty());
+    }
+    assert_eq!(map.metric(), Metric::Chebyshev);
+}
+
+#[test]
+fn ploc_strict() {
+    let mut map = ExtendedHashMap::from(TEST_DATA);
+
+    assert_eq!(map.try_ploc(">0 & >0").unwrap().len(), 3);
+    assert_eq!(map.try_ploc(">0, <"), Err(ParserErr::TokenMismatch));
+    assert!(map.ploc(">0, <").is_empty());
+
+    map.set_strict(true);
+
+    assert_eq!(
+        map.try_ploc(">0 & >0"),
+        Err(ParserErr::Lexer(LexerErr::UnexpectedChar('&', 3)))
+    );
+    assert_eq!(
+        map.try_ploc("= 'value1"),
+        Err(ParserErr::Lexer(LexerErr::UnterminatedLiteral(2)))
+    );
+    assert_eq!(map.try_ploc(">0; >0").unwrap(), map.ploc(">0, >0"));
+}
+
+#[test]
+fn diagnose() {
+    let mut map = ExtendedHashMap::from(TEST_DATA);
+
+    assert_eq!(map.diagnose(">0, <1"), None);
+    assert_eq!(
+        map.diagnose("$0 > 1, x lik 'a'").unwrap().to_string(),
+        "error: expected a comparison, found `lik`\n  \
+         | $0 > 1, x lik 'a'\n  \
+         |           ^^^\n  \
+         = help: did you mean `like`?"
+    );
+    assert_eq!(
+        map.try_ploc(">0, <").unwrap_err().to_string(),
+        "unexpected token"
+    );
+
+    assert_eq!(
+        map.diagnose_all("$0 >, = 'a', ~ 1")
+            .iter()
+            .map(|diagnostic| diagnostic.expected.as_str())
+            .collect::<Vec<_>>(),
+        ["a value", "a quoted pattern"]
+    );
+    assert!(map.diagnose_all(">0, <1").is_empty());
+    assert_eq!(
+        map.diagnose_all("(> 1, y lik 'a', > 2), > 0")
+            .iter()
+            .map(|diagnostic| diagnostic.found.as_str())
+            .collect::<Vec<_>>(),
+        ["`lik`"]
+    );
+
+    map.set_strict(true);
+
+    let diagnostic = map.diagnose(">0 & >0").unwrap();
+    assert_eq!((diagnostic.span.start, diagnostic.span.end), (3, 4));
+    assert_eq!(
+        map.try_ploc(">0 & >0").unwrap_err().to_string(),
+        "unexpected char `&` at byte 3"
+    );
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn ploc_regex() {
+    let map = ExtendedHashMap::from(TEST_DATA);
+
+    assert_eq!(
+        map.ploc(r"~ /^value[12]$/"),
+        HashMap::from([("value1", 1), ("value2", 2)])
+    );
+    assert_eq!(map.ploc(r"$0 ~ '\\d$'").len(), 3);
+    assert!(map.ploc("~ /(/").is_empty());
+}
+
 #[test]
 fn iter() {
     let map = ExtendedHashMap::from(TEST_DATA);